@@ -1,5 +1,7 @@
 package com.match.android
 
+import com.google.gson.annotations.SerializedName
+
 class JniApi {
     init {
         try {
@@ -12,6 +14,49 @@ class JniApi {
 
     external fun initLogger()
 
+    // Returns 1 on success, 0 if the config JSON is invalid
+    external fun bootstrap(configJson: String): Int
+
+    // P-521 key pair as JSON: {"private_key": "...", "public_key": "..."} (PEM). Empty on error.
+    external fun createKeyPair(): String
+
+    // Id for a new session (32 hex characters). Ids from elsewhere (e.g. deep links) are validated
+    // when creating or joining: status 11 if malformed.
+    external fun generateSessionId(): String
+
+    // Hex SHA-256 of the PEM public key, identifies the peer in the backend
+    external fun peerId(publicKey: String): String
+
+    // Raw P-521 signature (r || s, same as CryptoKit's rawRepresentation). Null on error.
+    external fun sign(privateKey: String, payload: ByteArray): ByteArray?
+    external fun verify(publicKey: String, payload: ByteArray, signature: ByteArray): Boolean
+
+    // BLE identity payload, encrypted for the peer (only they can validate it). Null on error.
+    external fun createIdentityPayload(privateKey: String, peerPublicKey: String): ByteArray?
+    // Whether the payload was created by the peer for us, recently and wasn't received before
+    external fun validateIdentityPayload(privateKey: String, peerPublicKey: String, payload: ByteArray): Boolean
+
+    // Colocated pairing: public key encrypted with the pairing password. Null on error.
+    external fun encryptPublicKey(publicKey: String, password: String): ByteArray?
+    // Null if the password is wrong or the data was modified
+    external fun decryptPublicKey(encryptedKey: ByteArray, password: String): String?
+
+    // Formats: 0 -> PEM, 1 -> DER, 2 -> compressed. Binary formats are base64url. Null on error.
+    external fun convertPublicKey(key: String, from: Int, to: Int): String?
+
+    // Code to compare with the peer: JSON {"digits": "012345", "emoji": [...]}, empty if a key is
+    // invalid
+    external fun shortAuthString(ownKey: String, peerKey: String): String
+    // 1 if the peer's code (digits or emoji) matches, 7 if not (key substituted), 0 if a key is
+    // invalid, otherwise error status
+    external fun checkShortAuthString(ownKey: String, peerKey: String, peerCode: String): Int
+
+    // Local session (id, key pair, peer, ready), as JSON including the private key, for the app to
+    // store. Returns the updated JSON, empty on error.
+    external fun createClientSession(sessionId: String, createdByMe: Boolean): String
+    external fun clientSessionWithPeer(sessionJson: String, peerPublicKey: String): String
+    external fun clientSessionWithIsReady(sessionJson: String, isReady: Boolean): String
+
     external fun add(value1: Long, value2: Int): Int
     external fun greet(who: String): String
 
@@ -19,6 +64,55 @@ class JniApi {
     external fun returnObject(): Dummy
 
     external fun registerCallback(callback: Callback)
+
+    // Async operations return a request id. The result is passed to the registered
+    // AsyncResultCallback with the same request id.
+    // Returns 1 on success, 0 if a callback was already registered
+    external fun registerAsyncResultCallback(callback: AsyncResultCallback): Int
+
+    // If there's already an active session: status 9 or, if reuseActive, the active session.
+    // Status 10 if the session id is used by another session.
+    external fun createSessionAsync(sessionId: String, key: String, privateKey: String, reuseActive: Boolean): Long
+    external fun joinSessionAsync(sessionId: String, key: String, privateKey: String): Long
+    // publicKey: own public key (the peer id is derived from it)
+    external fun ackAsync(publicKey: String, storedParticipants: Int, privateKey: String): Long
+    external fun participantsAsync(sessionId: String): Long
+    // publicKey: own public key (the peer id is derived from it)
+    external fun deleteAsync(publicKey: String, privateKey: String): Long
+
+    // Fetches participants, acks and marks the session as deleted when ready. Result JSON e.g.
+    // {"status":"peer_key_received","peer_key":"..."}. Status: waiting_for_peer,
+    // peer_key_received, waiting_for_peer_ack or ready. Ready has "delete_failed": true if the
+    // session couldn't be marked as deleted: retry with deleteAsync. peerKey: stored peer key, if any.
+    external fun refreshSessionAsync(sessionId: String, ownKey: String, ownPrivateKey: String, peerKey: String?): Long
+
+    // Session events are passed to the AsyncResultCallback with the returned request id and
+    // the event as JSON, e.g. {"type":"peer_joined","key":"..."}, {"type":"peer_acked"},
+    // {"type":"session_deleted"}. When the subscription ends, a last result with empty JSON is sent.
+    // Unsubscribe with cancel.
+    external fun subscribeSessionEvents(sessionId: String, ownKey: String): Long
+
+    // Cancels an async operation. Its result is delivered with status 6 (cancelled).
+    // Returns 1 if cancelled, 0 if there's no operation in progress with this id
+    external fun cancel(requestId: Long): Int
+
+    // Lifecycle state, e.g. "waiting_for_peer", "ready". Empty if the session wasn't joined since
+    // bootstrapping. Operations not valid in the state fail with status 8.
+    external fun sessionState(sessionId: String): String
+    // The meeting follows the pairing. Returns 1 on success, otherwise error status.
+    external fun startMeeting(sessionId: String): Int
+    external fun endMeeting(sessionId: String): Int
+
+    // The core stores the client session in the app's secure storage.
+    // Returns 1 on success, 0 if a storage was already registered
+    external fun registerSecureStorage(storage: CoreSecureStorage): Int
+    // Stores the client session JSON (see createClientSession), replacing the stored one.
+    // Returns 1 on success, otherwise error status.
+    external fun saveSession(sessionJson: String): Int
+    // Stored client session JSON, empty if there's none. Operations on the loaded session are
+    // validated against its state (see sessionState).
+    external fun loadSession(): LoadSessionResult
+    external fun clearSession(): Int
 }
 
 data class Dummy(
@@ -29,3 +123,29 @@ data class Dummy(
 interface Callback {
     fun call(string: String)
 }
+
+// E.g. backed by SecurePreferences. Called on the thread calling the core.
+interface CoreSecureStorage {
+    // Null if there's no value for the key
+    fun get(key: String): String?
+    // Return true on success
+    fun set(key: String, value: String): Boolean
+    fun remove(key: String): Boolean
+}
+
+data class CoreConfig(
+    @SerializedName("base_url")
+    val baseUrl: String
+)
+
+data class LoadSessionResult(
+    // 1 -> success, otherwise error status
+    val status: Int,
+    // Empty if there's no stored session or on error
+    val sessionJson: String
+)
+
+interface AsyncResultCallback {
+    // status: 1 -> success, otherwise error. resultJson is empty on error.
+    fun onResult(requestId: Long, status: Int, resultJson: String)
+}
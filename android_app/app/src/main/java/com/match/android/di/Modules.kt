@@ -13,6 +13,9 @@ import androidx.security.crypto.MasterKey.DEFAULT_MASTER_KEY_ALIAS
 import androidx.security.crypto.MasterKeys
 import com.google.gson.Gson
 import com.google.gson.GsonBuilder
+import com.match.android.BuildConfig
+import com.match.android.CoreConfig
+import com.match.android.JniApi
 import com.match.android.MainActivity
 import com.match.android.ble.BleEnabler
 import com.match.android.ble.BleEnablerImpl
@@ -60,6 +63,10 @@ val systemModule = module {
     single<NotificationShower> { NotificationsShowerImpl(get()) }
 }
 
+val coreModule = module {
+    single(createdAtStart = true) { bootstrappedJniApi(get()) }
+}
+
 val uiModule = module {
     single { MainNav() }
 }
@@ -78,6 +85,7 @@ val bleModule = module {
 val appModule = listOf(
     viewModelModule,
     systemModule,
+    coreModule,
     uiModule,
     bleModule
 )
@@ -85,6 +93,13 @@ val appModule = listOf(
 fun getSharedPrefs(androidApplication: Application): SharedPreferences =
     androidApplication.getSharedPreferences("default", MODE_PRIVATE)
 
+// The base url is set per build type (CORE_BASE_URL in build.gradle)
+private fun bootstrappedJniApi(gson: Gson): JniApi = JniApi().apply {
+    initLogger()
+    val configJson = gson.toJson(CoreConfig(baseUrl = BuildConfig.CORE_BASE_URL))
+    check(bootstrap(configJson) == 1) { "Couldn't bootstrap core, config: $configJson" }
+}
+
 private fun provideGson(): Gson = GsonBuilder()
     .serializeNulls()
     .setLenient()
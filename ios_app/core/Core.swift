@@ -1,27 +1,59 @@
 import Foundation
 import CryptoKit
+import Combine
 
 protocol Bootstrapper {
     func bootstrap() -> Result<Void, ServicesError>
 }
 
+// The requests run in the core and return right away. completion may be called on any thread,
+// with .cancelled if the returned Cancellable was cancelled (e.g. when leaving the pairing screen).
 protocol SessionApi {
-    func createSession(sessionId: SessionId, publicKey: PublicKey) -> Result<BackendSession, ServicesError>
-    func joinSession(id: SessionId, publicKey: PublicKey) -> Result<BackendSession, ServicesError>
-    func ackAndRequestSessionReady(peerId: PeerId, storedPeers: Int) -> Result<Bool, ServicesError>
-    func peers(sessionId: SessionId) -> Result<BackendSession, ServicesError>
-    func delete(peerId: PeerId) -> Result<(), ServicesError>
+    func generateSessionId() -> SessionId
+    // privateKey: signs the requests that change the backend session
+    func createSession(sessionId: SessionId, publicKey: PublicKey, privateKey: PrivateKey,
+                       completion: @escaping (Result<BackendSession, ServicesError>) -> Void) -> Cancellable
+    func joinSession(id: SessionId, publicKey: PublicKey, privateKey: PrivateKey,
+                     completion: @escaping (Result<BackendSession, ServicesError>) -> Void) -> Cancellable
+    // publicKey: own public key, the core derives the peer id from it
+    func ackAndRequestSessionReady(publicKey: PublicKey, storedPeers: Int, privateKey: PrivateKey,
+                                   completion: @escaping (Result<Bool, ServicesError>) -> Void) -> Cancellable
+    func peers(sessionId: SessionId,
+               completion: @escaping (Result<BackendSession, ServicesError>) -> Void) -> Cancellable
+    func delete(publicKey: PublicKey, privateKey: PrivateKey,
+                completion: @escaping (Result<(), ServicesError>) -> Void) -> Cancellable
+}
+
+struct CoreConfig: Encodable {
+    let baseUrl: String
+
+    enum CodingKeys: String, CodingKey {
+        case baseUrl = "base_url"
+    }
 }
 
 class CoreImpl: SessionApi, Bootstrapper {
+    private let config: CoreConfig
+
+    init(config: CoreConfig) {
+        self.config = config
+    }
 
     func bootstrap() -> Result<Void, ServicesError> {
         let registrationStatus = register_log_callback { logMessage in
             log(logMessage: logMessage)
         }
         NSLog("register_callback returned : %d", registrationStatus)
+        let asyncRegistrationStatus = register_async_result_callback { result in
+            AsyncResultDispatcher.shared.dispatch(result: result)
+        }
+        NSLog("register_async_result_callback returned : %d", asyncRegistrationStatus)
+        guard let configData = try? JSONEncoder().encode(config),
+              let configJson = String(data: configData, encoding: .utf8) else {
+            return .failure(.general("Critical: couldn't encode core config: \(config)"))
+        }
         // CoreLogLevel: 0 -> Trace... 4 -> Error
-        let res: Int32 = ffi_bootstrap(CoreLogLevel(0), true)
+        let res: Int32 = ffi_bootstrap(CoreLogLevel(0), true, configJson)
         if res == 1 {
             return .success(())
         } else {
@@ -29,56 +61,87 @@ class CoreImpl: SessionApi, Bootstrapper {
         }
     }
 
-    func createSession(sessionId: SessionId, publicKey: PublicKey) -> Result<BackendSession, ServicesError> {
-        let res = ffi_create_session(sessionId.value, publicKey.value)
-        switch res.status {
-        case 1: return decode(sessionJson: res.session_json)
-        case 2: return .failure(.networking("Networking error. Please try again later."))
-        default: return .failure(.general("Error creating session: \(res)"))
+    func generateSessionId() -> SessionId {
+        SessionId(value: ffi_generate_session_id().toString())
+    }
+
+    func createSession(sessionId: SessionId, publicKey: PublicKey, privateKey: PrivateKey,
+                       completion: @escaping (Result<BackendSession, ServicesError>) -> Void) -> Cancellable {
+        let requestId = ffi_create_session_async(sessionId.value, publicKey.value, privateKey.value, false)
+        return AsyncResultDispatcher.shared.register(requestId: requestId) { res in
+            switch res.status {
+            case 1: completion(self.decode(sessionJson: res.json))
+            case 2: completion(.failure(.networking("Networking error. Please try again later.")))
+            case 4: completion(.failure(.sessionFull))
+            case 6: completion(.failure(.cancelled))
+            case 9: completion(.failure(.general("Can't create session: there's already one.")))
+            default: completion(.failure(.general("Error creating session: \(res)")))
+            }
         }
     }
 
-    func joinSession(id: SessionId, publicKey: PublicKey) -> Result<BackendSession, ServicesError> {
+    func joinSession(id: SessionId, publicKey: PublicKey, privateKey: PrivateKey,
+                     completion: @escaping (Result<BackendSession, ServicesError>) -> Void) -> Cancellable {
         log.d("Will join session with id: \(id)")
-        let res = ffi_join_session(id.value, publicKey.value)
-        switch res.status {
-        case 1: return decode(sessionJson: res.session_json)
-        case 2: return .failure(.networking("Networking error. Please try again later."))
-        default: return .failure(.general("Error joining session: \(res)"))
+        let requestId = ffi_join_session_async(id.value, publicKey.value, privateKey.value)
+        return AsyncResultDispatcher.shared.register(requestId: requestId) { res in
+            switch res.status {
+            case 1: completion(self.decode(sessionJson: res.json))
+            case 2: completion(.failure(.networking("Networking error. Please try again later.")))
+            case 3: completion(.failure(.sessionNotFound))
+            case 4: completion(.failure(.sessionFull))
+            case 6: completion(.failure(.cancelled))
+            case 11: completion(.failure(.general("Invalid session id")))
+            default: completion(.failure(.general("Error joining session: \(res)")))
+            }
         }
     }
 
-    func ackAndRequestSessionReady(peerId: PeerId, storedPeers: Int) -> Result<Bool, ServicesError> {
-        log.d("Will ack and request session ready, peerId: \(peerId), storedPeers: \(storedPeers)")
-        let res = ffi_ack(peerId.value, Int32(storedPeers))
-        switch res.status {
-        case 1: return .success(res.is_ready)
-        case 2: return .failure(.networking("Networking error. Please try again later."))
-        default: return .failure(.general("Error acking session: \(res)"))
+    func ackAndRequestSessionReady(publicKey: PublicKey, storedPeers: Int, privateKey: PrivateKey,
+                                   completion: @escaping (Result<Bool, ServicesError>) -> Void) -> Cancellable {
+        log.d("Will ack and request session ready, storedPeers: \(storedPeers)")
+        let requestId = ffi_ack_async(publicKey.value, Int32(storedPeers), privateKey.value)
+        return AsyncResultDispatcher.shared.register(requestId: requestId) { res in
+            switch res.status {
+            case 1: completion(decodeAck(json: res.json))
+            case 2: completion(.failure(.networking("Networking error. Please try again later.")))
+            case 3: completion(.failure(.sessionNotFound))
+            case 5: completion(.failure(.alreadyAcked))
+            case 6: completion(.failure(.cancelled))
+            default: completion(.failure(.general("Error acking session: \(res)")))
+            }
         }
     }
 
-    func peers(sessionId: SessionId) -> Result<BackendSession, ServicesError> {
-        let res = ffi_participants(sessionId.value)
-        switch res.status {
-        case 1: return decode(sessionJson: res.session_json)
-        case 2: return .failure(.networking("Networking error. Please try again later."))
-        default: return .failure(.general("Error fetching peers: \(res)"))
+    func peers(sessionId: SessionId,
+               completion: @escaping (Result<BackendSession, ServicesError>) -> Void) -> Cancellable {
+        let requestId = ffi_participants_async(sessionId.value)
+        return AsyncResultDispatcher.shared.register(requestId: requestId) { res in
+            switch res.status {
+            case 1: completion(self.decode(sessionJson: res.json))
+            case 2: completion(.failure(.networking("Networking error. Please try again later.")))
+            case 3: completion(.failure(.sessionNotFound))
+            case 6: completion(.failure(.cancelled))
+            default: completion(.failure(.general("Error fetching peers: \(res)")))
+            }
         }
     }
 
-    func delete(peerId: PeerId) -> Result<(), ServicesError> {
-        let res = ffi_delete(peerId.value)
-        switch res.status {
-        case 1: return .success(())
-        case 2: return .failure(.networking("Networking error. Please try again later."))
-        default: return .failure(.general("Error marking as deleted: \(res)"))
+    func delete(publicKey: PublicKey, privateKey: PrivateKey,
+                completion: @escaping (Result<(), ServicesError>) -> Void) -> Cancellable {
+        let requestId = ffi_delete_async(publicKey.value, privateKey.value)
+        return AsyncResultDispatcher.shared.register(requestId: requestId) { res in
+            switch res.status {
+            case 1: completion(.success(()))
+            case 2: completion(.failure(.networking("Networking error. Please try again later.")))
+            case 3: completion(.failure(.sessionNotFound))
+            case 6: completion(.failure(.cancelled))
+            default: completion(.failure(.general("Error marking as deleted: \(res)")))
+            }
         }
     }
 
-    private func decode(sessionJson: Unmanaged<CFString>) -> Result<BackendSession, ServicesError> {
-        let resultString = sessionJson.toString()
-
+    private func decode(sessionJson resultString: String) -> Result<BackendSession, ServicesError> {
         log.d("Deserializing core result: \(resultString)")
 
         guard let data = resultString.data(using: .utf8) else {
@@ -103,6 +166,80 @@ private struct FFISession: Decodable {
     let keys: [String]
 }
 
+// e.g. {"is_ready": true}
+private struct AckResult: Decodable {
+    let is_ready: Bool
+}
+
+private func decodeAck(json: String) -> Result<Bool, ServicesError> {
+    guard let data = json.data(using: .utf8),
+          let ackResult = try? JSONDecoder().decode(AckResult.self, from: data) else {
+        return .failure(.general("Core returned invalid ack JSON: \(json)"))
+    }
+    return .success(ackResult.is_ready)
+}
+
+// Result of an async core request (FFIAsyncResult), json is empty on error
+private struct AsyncCoreResult {
+    let status: Int32
+    let json: String
+}
+
+// A request in progress in the core
+private class CoreRequest: Cancellable {
+    private let requestId: RequestId
+
+    init(requestId: RequestId) {
+        self.requestId = requestId
+    }
+
+    // The completion is called with status 6 (cancelled), unless the request already finished
+    func cancel() {
+        let wasInProgress = ffi_cancel(requestId) == 1
+        log.d("Cancelled core request: \(requestId), was in progress: \(wasInProgress)")
+    }
+}
+
+// Maps the results delivered to the async result callback to the completions of their requests.
+// The core may deliver a result before the request id is registered, so those are kept until then.
+private class AsyncResultDispatcher {
+    static let shared = AsyncResultDispatcher()
+
+    private let lock = NSLock()
+    private var completions: [RequestId: (AsyncCoreResult) -> Void] = [:]
+    private var undelivered: [RequestId: AsyncCoreResult] = [:]
+
+    func register(requestId: RequestId, completion: @escaping (AsyncCoreResult) -> Void) -> Cancellable {
+        lock.lock()
+        let result = undelivered.removeValue(forKey: requestId)
+        if result == nil {
+            completions[requestId] = completion
+        }
+        lock.unlock()
+
+        if let result = result {
+            // Not on the calling thread, so the caller gets the Cancellable before the completion runs
+            DispatchQueue.global().async {
+                completion(result)
+            }
+        }
+        return CoreRequest(requestId: requestId)
+    }
+
+    func dispatch(result: FFIAsyncResult) {
+        let coreResult = AsyncCoreResult(status: result.status, json: result.result_json.toString())
+
+        lock.lock()
+        let completion = completions.removeValue(forKey: result.request_id)
+        if completion == nil {
+            undelivered[result.request_id] = coreResult
+        }
+        lock.unlock()
+
+        completion?(coreResult)
+    }
+}
+
 extension Unmanaged where Instance == CFString {
     func toString() -> String {
         let resultValue: CFString = takeRetainedValue()
@@ -9,6 +9,15 @@ enum CoreLogLevel {
 };
 typedef uint8_t CoreLogLevel;
 
+enum KeyFormat {
+  Pem = 0,
+  Der = 1,
+  Compressed = 2,
+};
+typedef uint8_t KeyFormat;
+
+typedef uint64_t RequestId;
+
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
 typedef struct {
   int32_t status;
@@ -16,6 +25,86 @@ typedef struct {
 } FFIAckResult;
 #endif
 
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  CFStringRef signature;
+} FFISignResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  CFStringRef payload;
+} FFIIdentityPayloadResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  CFStringRef encrypted_key;
+} FFIEncryptedKeyResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  CFStringRef public_key;
+} FFIDecryptedKeyResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  CFStringRef key;
+} FFIConvertedKeyResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  CFStringRef digits;
+  CFStringRef emoji;
+} FFIShortAuthStringResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  bool is_valid;
+} FFIVerifyResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  CFStringRef session_json;
+} FFIClientSessionResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t status;
+  CFStringRef result_json;
+} FFIRefreshSessionResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  RequestId request_id;
+  int32_t status;
+  CFStringRef result_json;
+} FFIAsyncResult;
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+typedef struct {
+  int32_t (*get)(const char *key, char **value);
+  int32_t (*set)(const char *key, const char *value);
+  int32_t (*remove)(const char *key);
+} FFISecureStorage;
+#endif
+
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
 typedef struct {
   int32_t status;
@@ -71,11 +160,52 @@ int32_t add_values(int32_t value1, int32_t value2);
 #endif
 
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
-FFIAckResult ffi_ack(const char *uuid, int32_t stored_participants);
+__attribute__((deprecated("Blocking and not cancellable, use ffi_ack_async")))
+FFIAckResult ffi_ack(const char *public_key, int32_t stored_participants, const char *private_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+RequestId ffi_ack_async(const char *public_key, int32_t stored_participants, const char *private_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t ffi_bootstrap(CoreLogLevel level, bool app_only, const char *config_json);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t ffi_cancel(RequestId request_id);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t ffi_check_short_auth_string(const char *own_key,
+                                    const char *peer_key,
+                                    const char *peer_code);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t ffi_clear_session(void);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIClientSessionResult ffi_client_session_with_is_ready(const char *session_json, bool is_ready);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIClientSessionResult ffi_client_session_with_peer(const char *session_json,
+                                                    const char *peer_public_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIConvertedKeyResult ffi_convert_public_key(const char *key, KeyFormat from, KeyFormat to);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIClientSessionResult ffi_create_client_session(const char *session_id, bool created_by_me);
 #endif
 
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
-int32_t ffi_bootstrap(CoreLogLevel level, bool app_only);
+FFIIdentityPayloadResult ffi_create_identity_payload(const char *private_key,
+                                                     const char *peer_public_key);
 #endif
 
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
@@ -83,21 +213,131 @@ FFIKeyPairResult ffi_create_key_pair(void);
 #endif
 
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
-FFISessionResult ffi_create_session(const char *session_id, const char *key);
+__attribute__((deprecated("Blocking and not cancellable, use ffi_create_session_async")))
+FFISessionResult ffi_create_session(const char *session_id,
+                                    const char *key,
+                                    const char *private_key,
+                                    bool reuse_active);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+RequestId ffi_create_session_async(const char *session_id,
+                                   const char *key,
+                                   const char *private_key,
+                                   bool reuse_active);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIDecryptedKeyResult ffi_decrypt_public_key(const uint8_t *encrypted_key,
+                                             uintptr_t encrypted_key_len,
+                                             const char *password);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+__attribute__((deprecated("Blocking and not cancellable, use ffi_delete_async")))
+FFIDeleteResult ffi_delete(const char *public_key, const char *private_key);
 #endif
 
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
-FFIDeleteResult ffi_delete(const char *peer_id);
+RequestId ffi_delete_async(const char *public_key, const char *private_key);
 #endif
 
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
-FFISessionResult ffi_join_session(const char *session_id, const char *key);
+FFIEncryptedKeyResult ffi_encrypt_public_key(const char *public_key, const char *password);
 #endif
 
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t ffi_end_meeting(const char *session_id);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+CFStringRef ffi_generate_session_id(void);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+__attribute__((deprecated("Blocking and not cancellable, use ffi_join_session_async")))
+FFISessionResult ffi_join_session(const char *session_id,
+                                  const char *key,
+                                  const char *private_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+RequestId ffi_join_session_async(const char *session_id,
+                                 const char *key,
+                                 const char *private_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIClientSessionResult ffi_load_session(void);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+__attribute__((deprecated("Blocking and not cancellable, use ffi_participants_async")))
 FFIParticipantsResult ffi_participants(const char *session_id);
 #endif
 
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+RequestId ffi_participants_async(const char *session_id);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+CFStringRef ffi_peer_id(const char *public_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+__attribute__((deprecated("Blocking and not cancellable, use ffi_refresh_session_async")))
+FFIRefreshSessionResult ffi_refresh_session(const char *session_id,
+                                            const char *own_key,
+                                            const char *own_private_key,
+                                            const char *peer_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+RequestId ffi_refresh_session_async(const char *session_id,
+                                    const char *own_key,
+                                    const char *own_private_key,
+                                    const char *peer_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t ffi_save_session(const char *session_json);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+CFStringRef ffi_session_state(const char *session_id);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIShortAuthStringResult ffi_short_auth_string(const char *own_key, const char *peer_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFISignResult ffi_sign(const char *private_key, const uint8_t *payload, uintptr_t payload_len);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t ffi_start_meeting(const char *session_id);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+RequestId ffi_subscribe_session_events(const char *session_id, const char *own_key);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIVerifyResult ffi_validate_identity_payload(const char *private_key,
+                                              const char *peer_public_key,
+                                              const uint8_t *payload,
+                                              uintptr_t payload_len);
+#endif
+
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+FFIVerifyResult ffi_verify(const char *public_key,
+                           const uint8_t *payload,
+                           uintptr_t payload_len,
+                           const uint8_t *signature,
+                           uintptr_t signature_len);
+#endif
+
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
 CFStringRef greet(const char *who);
 #endif
@@ -106,6 +346,10 @@ CFStringRef greet(const char *who);
 void pass_struct(const ParamStruct *object);
 #endif
 
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t register_async_result_callback(void (*callback)(FFIAsyncResult));
+#endif
+
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
 void register_callback(void (*callback)(CFStringRef));
 #endif
@@ -114,6 +358,10 @@ void register_callback(void (*callback)(CFStringRef));
 int32_t register_log_callback(void (*log_callback)(CoreLogMessage));
 #endif
 
+#if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
+int32_t register_secure_storage(FFISecureStorage storage);
+#endif
+
 #if (defined(TARGET_OS_IOS) || defined(TARGET_OS_MACOS))
 ReturnStruct return_struct(void);
 #endif
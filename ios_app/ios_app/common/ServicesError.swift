@@ -3,4 +3,10 @@ import Foundation
 public enum ServicesError: Error, Equatable {
     case general(_ message: String)
     case networking(_ message: String)
+    // Backend error codes (see the core's CoreError)
+    case sessionNotFound
+    case sessionFull
+    case alreadyAcked
+    // The request was cancelled (e.g. when leaving the pairing screen)
+    case cancelled
  }
@@ -9,22 +9,10 @@ struct PublicKey: Codable, Equatable {
     let value: String // P521 PEM representation
 }
 
-extension PublicKey {
-    // Peer here is generic, it can refer to my peer or me as a peer
-    func toPeerId(crypto: Crypto) -> PeerId {
-        PeerId(value: crypto.sha256(str: value))
-    }
-}
-
 struct PrivateKey: Codable, Equatable {
     let value: String // P521 PEM representation
 }
 
-// Public key hash
-struct PeerId: Codable, Equatable {
-    let value: String
-}
-
 struct Peer: Codable, Equatable {
     let publicKey: PublicKey
 }
@@ -51,7 +39,6 @@ struct Session: Codable, Equatable {
     let id: SessionId
     let privateKey: PrivateKey
     let publicKey: PublicKey
-    let peerId: PeerId // My own peer id
     let createdByMe: Bool // Whether I created the session (opposed to join)
     let peer: Peer? // Peer. Nil if they've not joined the session yet
     let isReady: Bool // Whether both peers have ack-ed the public key of the other
@@ -61,7 +48,6 @@ struct Session: Codable, Equatable {
             id: id,
             privateKey: privateKey,
             publicKey: publicKey,
-            peerId: peerId,
             createdByMe: createdByMe,
             peer: peer,
             isReady: isReady
@@ -73,7 +59,6 @@ struct Session: Codable, Equatable {
             id: id,
             privateKey: privateKey,
             publicKey: publicKey,
-            peerId: peerId,
             createdByMe: createdByMe,
             peer: peer,
             isReady: isReady
@@ -40,7 +40,12 @@ class Dependencies {
     }
 
     private func registerCore(container: DependencyContainer) {
-        let core = CoreImpl()
+        // Set per build configuration (CORE_BASE_URL build setting, see Info.plist)
+        guard let baseUrl = Bundle.main.object(forInfoDictionaryKey: "CoreBaseUrl") as? String,
+              !baseUrl.isEmpty else {
+            fatalError("CRITICAL: CoreBaseUrl missing in Info.plist")
+        }
+        let core = CoreImpl(config: CoreConfig(baseUrl: baseUrl))
         let res = core.bootstrap()
         if res.isFailure() {
             fatalError("CRITICAL: Couldn't initialize core: \(res)")
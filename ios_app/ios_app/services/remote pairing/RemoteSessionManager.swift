@@ -1,9 +1,11 @@
 import Foundation
+import Combine
 
+// The returned Cancellable cancels the request in progress (e.g. when leaving the pairing screen)
 protocol RemoteSessionManager {
-    func create()
-    func join(sessionId: SessionId)
-    func refresh()
+    @discardableResult func create() -> Cancellable
+    @discardableResult func join(sessionId: SessionId) -> Cancellable
+    @discardableResult func refresh() -> Cancellable
     func delete() -> Result<(), ServicesError>
 }
 
@@ -16,19 +18,19 @@ class RemoteSessionManagerImpl: RemoteSessionManager {
         self.currentSessionService = currentSessionService
     }
 
-    func create() {
+    @discardableResult func create() -> Cancellable {
         log.d("Creating session", .session)
         currentSessionService.setSessionState(.progress)
-        DispatchQueue.global(qos: .background).async {
-            self.handleCreateOrJoinSessionStateResult((self.sessionService.createSession()))
+        return sessionService.createSession { result in
+            self.handleCreateOrJoinSessionStateResult(result)
         }
     }
 
-    func join(sessionId: SessionId) {
+    @discardableResult func join(sessionId: SessionId) -> Cancellable {
         log.d("Joining session: \(sessionId)", .session)
         currentSessionService.setSessionState(.progress)
-        DispatchQueue.global(qos: .background).async {
-            self.handleCreateOrJoinSessionStateResult((self.sessionService.joinSession(id: sessionId)))
+        return sessionService.joinSession(id: sessionId) { result in
+            self.handleCreateOrJoinSessionStateResult(result)
         }
     }
 
@@ -36,12 +38,12 @@ class RemoteSessionManagerImpl: RemoteSessionManager {
         currentSessionService.setSessionState(.result(result.map { .isSet($0) }))
     }
 
-    func refresh() {
+    @discardableResult func refresh() -> Cancellable {
         log.d("Refreshing session", .session)
         // Note: no progress state. This is only for visuals (show progress indicator) and refresh
         // done in the background.
-        DispatchQueue.global(qos: .background).async {
-            self.currentSessionService.setSessionState(.result(self.sessionService.refreshSession().map { .isSet($0) }))
+        return sessionService.refreshSession { result in
+            self.currentSessionService.setSessionState(.result(result.map { .isSet($0) }))
         }
     }
 
@@ -1,8 +1,11 @@
 import Foundation
+import Combine
 
+// The completions may be called on any thread. Cancelling the returned Cancellable cancels
+// the request in progress: the completion is called with .cancelled.
 protocol RemoteSessionService {
-    func createSession() -> Result<Session, ServicesError>
-    func joinSession(id: SessionId) -> Result<Session, ServicesError>
+    func createSession(completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable
+    func joinSession(id: SessionId, completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable
 
     /**
      * Retrieves the backend's session data (i.e. peer's public key) and acks to the backend
@@ -22,7 +25,7 @@ protocol RemoteSessionService {
      *
      * @returns whether session is ready, i.e. both peers have ack-ed having stored the peer's public key
      */
-    func refreshSession() -> Result<Session, ServicesError>
+    func refreshSession(completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable
 }
 
 class RemoteSessionServiceImpl: RemoteSessionService {
@@ -34,58 +37,86 @@ class RemoteSessionServiceImpl: RemoteSessionService {
         self.localSessionManager = localSessionManager
     }
 
-    func createSession() -> Result<Session, ServicesError> {
-//        guard !hasActiveSession() else {
-//            return .failure(.general("Can't create session: there's already one."))
-//        }
-        localSessionManager.initLocalSession(iCreatedIt: true,
-                                             sessionIdGenerator: { SessionId(
-                                                value: UUID().uuidString.removeAllImmutable(where: { $0 == "-" })
-                                             ) }).flatMap { session in
-            switch sessionApi
-                .createSession(sessionId: session.id, publicKey: session.publicKey) {
-            case .success(let backendSession):
-                // when creating the session, there will be obviously no peer yet (so no ack etc.)
-                // we use the same handler as the rest for consistency, as it's the same response.
-                return handleSessionResult(backendSession: backendSession, session: session)
-            case .failure(let e):
-                log.e("Error creating backend session. Deleting local session", .session)
-                if case .failure(e) = localSessionManager.clear() {
-                    log.e("Error deleting local session: \(e)", .session)
+    func createSession(completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable {
+        let requests = RequestSequence()
+        // The core fails if there's already an active session
+        let sessionRes = localSessionManager.initLocalSession(iCreatedIt: true,
+                                                              sessionIdGenerator: { sessionApi.generateSessionId() })
+        switch sessionRes {
+        case .success(let session):
+            requests.add(sessionApi.createSession(sessionId: session.id, publicKey: session.publicKey,
+                                                  privateKey: session.privateKey) { res in
+                switch res {
+                case .success(let backendSession):
+                    // when creating the session, there will be obviously no peer yet (so no ack etc.)
+                    // we use the same handler as the rest for consistency, as it's the same response.
+                    self.handleSessionResult(backendSession: backendSession, session: session, requests: requests,
+                                             completion: completion)
+                case .failure(let e):
+                    log.e("Error creating backend session. Deleting local session", .session)
+                    if case .failure(e) = self.localSessionManager.clear() {
+                        log.e("Error deleting local session: \(e)", .session)
+                    }
+                    completion(.failure(e))
                 }
-                return .failure(e)
-            }
-         }
+            })
+        case .failure(let e):
+            completion(.failure(e))
+        }
+        return requests
     }
 
-    func joinSession(id sessionId: SessionId) -> Result<Session, ServicesError> {
-        loadOrCreateSession(isCreate: false, sessionIdGenerator: { sessionId }).flatMap {
-            joinSession(id: sessionId, session: $0)
+    func joinSession(id sessionId: SessionId,
+                     completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable {
+        let requests = RequestSequence()
+        switch loadOrCreateSession(isCreate: false, sessionIdGenerator: { sessionId }) {
+        case .success(let session):
+            joinSession(id: sessionId, session: session, requests: requests, completion: completion)
+        case .failure(let e):
+            completion(.failure(e))
         }
+        return requests
     }
 
-    func refreshSession() -> Result<Session, ServicesError> {
-        localSessionManager.withSession {
-            refreshSession($0)
+    func refreshSession(completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable {
+        let requests = RequestSequence()
+        switch localSessionManager.withSession(f: { .success($0) }) {
+        case .success(let session):
+            refreshSession(session, requests: requests, completion: completion)
+        case .failure(let e):
+            completion(.failure(e))
         }
+        return requests
     }
 
     // MARK: private
 
-    private func joinSession(id sessionId: SessionId,
-                             session: Session) -> Result<Session, ServicesError> {
-        sessionApi
-            // Join returns the current peers too (like the peers call)
-            .joinSession(id: sessionId, publicKey: session.publicKey)
-            .flatMap { backendSession in
-                handleSessionResult(backendSession: backendSession, session: session)
+    private func joinSession(id sessionId: SessionId, session: Session, requests: RequestSequence,
+                             completion: @escaping (Result<Session, ServicesError>) -> Void) {
+        // Join returns the current peers too (like the peers call)
+        requests.add(sessionApi.joinSession(id: sessionId, publicKey: session.publicKey,
+                                            privateKey: session.privateKey) { res in
+            switch res {
+            case .success(let backendSession):
+                self.handleSessionResult(backendSession: backendSession, session: session, requests: requests,
+                                         completion: completion)
+            case .failure(let e):
+                completion(.failure(e))
             }
+        })
     }
 
-    private func refreshSession(_ session: Session) -> Result<Session, ServicesError> {
-        sessionApi.peers(sessionId: session.id).flatMap { backendSession in
-            handleSessionResult(backendSession: backendSession, session: session)
-        }
+    private func refreshSession(_ session: Session, requests: RequestSequence,
+                                completion: @escaping (Result<Session, ServicesError>) -> Void) {
+        requests.add(sessionApi.peers(sessionId: session.id) { res in
+            switch res {
+            case .success(let backendSession):
+                self.handleSessionResult(backendSession: backendSession, session: session, requests: requests,
+                                         completion: completion)
+            case .failure(let e):
+                completion(.failure(e))
+            }
+        })
     }
 
     /**
@@ -94,44 +125,45 @@ class RemoteSessionServiceImpl: RemoteSessionService {
      * - Acks to backend that we stored the key
      * - Marks session as deleted if ack returns that session is ready (both peers ack-ed)
      */
-    private func handleSessionResult(backendSession: BackendSession,
-                                     session: Session) -> Result<Session, ServicesError> {
-        storePeerIfPresentAndAck(backendSession: backendSession, session: session).flatMap { ready in
-            return localSessionManager.saveIsReady(ready).flatMap { session in
-                log.d("Updated local session ready status: \(ready)", .session)
-                if ready {
-                        switch markDeleted(session: session) {
-                        case .success: return .success(session)
-                        case .failure(let e): return .failure(e)
-                        }
+    private func handleSessionResult(backendSession: BackendSession, session: Session, requests: RequestSequence,
+                                     completion: @escaping (Result<Session, ServicesError>) -> Void) {
+        storePeerIfPresentAndAck(backendSession: backendSession, session: session,
+                                 requests: requests) { ackRes in
+            switch ackRes.flatMap({ ready in self.localSessionManager.saveIsReady(ready) }) {
+            case .success(let session):
+                log.d("Updated local session ready status: \(session.isReady)", .session)
+                if session.isReady {
+                    self.markDeleted(session: session, requests: requests) { res in
+                        completion(res.map { session })
+                    }
                 } else {
-                    return .success(session)
+                    completion(.success(session))
                 }
+            case .failure(let e):
+                completion(.failure(e))
             }
         }
     }
 
-    private func markDeleted(session: Session) -> Result<(), ServicesError> {
-        let peerId = session.peerId
-        let res = sessionApi.delete(peerId: peerId)
-        switch res {
-        case .success: log.d("Mark deleted success for peer id: \(peerId)", .session)
-        case .failure(let e): log.e("Didn't succeed deleting session: \(e)", .session)
-        }
-        return res
-    }
-
-    private func ackAndRequestSessionReady() -> Result<Bool, ServicesError> {
-        localSessionManager.withSession {
-            ackAndRequestSessionReady(session: $0)
-        }
+    private func markDeleted(session: Session, requests: RequestSequence,
+                             completion: @escaping (Result<(), ServicesError>) -> Void) {
+        requests.add(sessionApi.delete(publicKey: session.publicKey, privateKey: session.privateKey) { res in
+            switch res {
+            case .success: log.d("Mark deleted success for session: \(session.id)", .session)
+            case .failure(let e): log.e("Didn't succeed deleting session: \(e)", .session)
+            }
+            completion(res)
+        })
     }
 
-    private func ackAndRequestSessionReady(session: Session) -> Result<Bool, ServicesError> {
-        sessionApi.ackAndRequestSessionReady(
-            peerId: session.peerId,
-            storedPeers: session.hasPeer() ? 2 : 1
-        )
+    private func ackAndRequestSessionReady(session: Session, requests: RequestSequence,
+                                           completion: @escaping (Result<Bool, ServicesError>) -> Void) {
+        requests.add(sessionApi.ackAndRequestSessionReady(
+            publicKey: session.publicKey,
+            storedPeers: session.hasPeer() ? 2 : 1,
+            privateKey: session.privateKey,
+            completion: completion
+        ))
     }
 
     private func processBackendSession(_ backendSession: BackendSession) -> Result<(), ServicesError> {
@@ -150,21 +182,22 @@ class RemoteSessionServiceImpl: RemoteSessionService {
         }
     }
 
-    private func storePeerIfPresentAndAck(backendSession: BackendSession,
-                                          session: Session) -> Result<Bool, ServicesError> {
+    private func storePeerIfPresentAndAck(backendSession: BackendSession, session: Session,
+                                          requests: RequestSequence,
+                                          completion: @escaping (Result<Bool, ServicesError>) -> Void) {
         if let peer = backendSession.determinePeer(session: session) {
             switch localSessionManager.savePeer(peer) {
-            case .success:
-                return ackAndRequestSessionReady()
+            case .success(let session):
+                ackAndRequestSessionReady(session: session, requests: requests, completion: completion)
             case .failure(let e):
                 let msg = "Error storing peer: \(e)"
                 log.e(msg, .session)
-                return .failure(.general(msg))
+                completion(.failure(.general(msg)))
             }
         } else {
             log.v("The backend session: \(backendSession) doesn't have a peer yet. Session isn't ready.",
                   .session)
-            return .success(false)
+            completion(.success(false))
         }
     }
 
@@ -182,6 +215,31 @@ class RemoteSessionServiceImpl: RemoteSessionService {
     }
 }
 
+// Cancels the request in progress of a sequence of requests (e.g. join, ack, delete).
+// Requests added after cancelling are cancelled right away.
+private class RequestSequence: Cancellable {
+    private let lock = NSLock()
+    private var current: Cancellable?
+    private var isCancelled = false
+
+    func add(_ request: Cancellable) {
+        lock.lock()
+        defer { lock.unlock() }
+        if isCancelled {
+            request.cancel()
+        } else {
+            current = request
+        }
+    }
+
+    func cancel() {
+        lock.lock()
+        defer { lock.unlock() }
+        isCancelled = true
+        current?.cancel()
+    }
+}
+
 private extension BackendSession {
     func determinePeer(session: Session) -> Peer? {
         guard keys.count < 3 else {
@@ -220,16 +278,19 @@ class NoopRemoteSessionService: RemoteSessionService {
         .success(())
     }
 
-    func createSession() -> Result<Session, ServicesError> {
-        .failure(.general("Noop failure"))
+    func createSession(completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable {
+        completion(.failure(.general("Noop failure")))
+        return AnyCancellable {}
     }
 
-    func joinSession(id: SessionId) -> Result<Session, ServicesError> {
-        .failure(.general("Noop failure"))
+    func joinSession(id: SessionId, completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable {
+        completion(.failure(.general("Noop failure")))
+        return AnyCancellable {}
     }
 
-    func refreshSession() -> Result<Session, ServicesError> {
-        .failure(.general("Noop failure"))
+    func refreshSession(completion: @escaping (Result<Session, ServicesError>) -> Void) -> Cancellable {
+        completion(.failure(.general("Noop failure")))
+        return AnyCancellable {}
     }
 
     func currentSession() -> Result<Session?, ServicesError> {
@@ -45,7 +45,6 @@ class LocalSessionManagerImpl: LocalSessionManager {
             id: sessionIdGenerator(),
             privateKey: keyPair.privateKey,
             publicKey: keyPair.publicKey,
-            peerId: keyPair.publicKey.toPeerId(crypto: crypto),
             createdByMe: isCreate,
             peer: nil,
             isReady: false
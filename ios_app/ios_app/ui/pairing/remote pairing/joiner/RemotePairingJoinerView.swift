@@ -45,6 +45,7 @@ struct RemotePairingJoinerView: View {
             }
         }
         .navigationBarTitle(Text("Join session"), displayMode: .inline)
+        .onDisappear { viewModel.onDisappear() }
         .navigationBarItems(trailing: Button(action: { [weak viewModel] in
             viewModel?.onSettingsButtonTap()
         }) { SettingsImage() })
@@ -15,6 +15,8 @@ class RemotePairingJoinerViewModel: ObservableObject {
     private let observeSession = CurrentValueSubject<Bool, Never>(false)
 
     private var sessionCancellable: Cancellable?
+    // Join request in progress, cancelled when leaving the screen
+    private var joinRequest: Cancellable?
 
     init(sessionManager: RemoteSessionManager, sessionService: CurrentSessionService, clipboard: Clipboard,
          uiNotifier: UINotifier) {
@@ -45,10 +47,16 @@ class RemotePairingJoinerViewModel: ObservableObject {
             let msg = "Couldn't retrieve session: \(e)"
             log.e(msg, .ui)
             switch e {
-            case .general:
+            case .general, .alreadyAcked:
                 uiNotifier.show(.error("Error joining session. Please try again."))
+            case .cancelled:
+                log.d("Joining session cancelled", .ui)
             case .networking(let msg):
                 uiNotifier.show(.error(msg))
+            case .sessionNotFound:
+                uiNotifier.show(.error("Session not found. Please check the link."))
+            case .sessionFull:
+                uiNotifier.show(.error("Session is full."))
             }
             observeSession.send(false)
             showLoading = false
@@ -77,7 +85,12 @@ class RemotePairingJoinerViewModel: ObservableObject {
             return
         }
         observeSession.send(true)
-        sessionManager.join(sessionId: sessionLink.sessionId)
+        joinRequest = sessionManager.join(sessionId: sessionLink.sessionId)
+    }
+
+    func onDisappear() {
+        joinRequest?.cancel()
+        joinRequest = nil
     }
     
     func onSettingsButtonTap() {
@@ -90,5 +103,6 @@ class RemotePairingJoinerViewModel: ObservableObject {
 
     deinit {
         log.d("View model deinit", .ui)
+        joinRequest?.cancel()
     }
 }
@@ -35,6 +35,7 @@ struct RemotePairingRoleSelectionView: View {
             }
         }
         .navigationBarTitle(Text("Select role"), displayMode: .inline)
+        .onDisappear { viewModel.onDisappear() }
         .navigationBarItems(trailing: Button(action: { [weak viewModel] in
             viewModel?.onSettingsButtonTap()
         }) { SettingsImage() })
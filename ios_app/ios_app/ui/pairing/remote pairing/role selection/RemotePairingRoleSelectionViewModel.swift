@@ -19,6 +19,8 @@ class RemotePairingRoleSelectionViewModel: ObservableObject {
     private let observeSession = CurrentValueSubject<Bool, Never>(false)
 
     private var sessionCancellable: AnyCancellable?
+    // Create request in progress, cancelled when leaving the screen
+    private var createRequest: Cancellable?
 
     init(remoteSessionManager: RemoteSessionManager, sessionService: CurrentSessionService, uiNotifier: UINotifier) {
         self.remoteSessionManager = remoteSessionManager
@@ -49,8 +51,10 @@ class RemotePairingRoleSelectionViewModel: ObservableObject {
         case .result(.failure(let e)):
             log.e("Current session error: \(e)", .session, .ui)
             switch e {
-            case .general:
+            case .general, .sessionNotFound, .sessionFull, .alreadyAcked:
                 uiNotifier.show(.error("Error creating session. Please try again."))
+            case .cancelled:
+                log.d("Creating session cancelled", .ui)
             case .networking(let msg):
                 uiNotifier.show(.error(msg))
             }
@@ -64,7 +68,7 @@ class RemotePairingRoleSelectionViewModel: ObservableObject {
 
     func onCreateSessionTap() {
         observeSession.send(true)
-        remoteSessionManager.create()
+        createRequest = remoteSessionManager.create()
     }
 
     func onJoinSessionTap() {
@@ -74,6 +78,11 @@ class RemotePairingRoleSelectionViewModel: ObservableObject {
     func onSettingsButtonTap() {
         showSettingsModal = true
     }
+
+    func onDisappear() {
+        createRequest?.cancel()
+        createRequest = nil
+    }
     
     private func navigate(to: RemotePairingRoleDestination) {
         log.d("Navigating to: \(to)", .ui)
@@ -83,6 +92,7 @@ class RemotePairingRoleSelectionViewModel: ObservableObject {
 
     deinit {
         log.d("View model deinit", .ui)
+        createRequest?.cancel()
     }
 }
 
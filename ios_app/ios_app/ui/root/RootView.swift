@@ -57,8 +57,8 @@ class NoopBleEnabler: BleEnabler {
 }
 
 class NoopRemoteSessionManager: RemoteSessionManager {
-    func create() {}
-    func join(sessionId: SessionId) {}
-    func refresh() {}
+    func create() -> Cancellable { AnyCancellable {} }
+    func join(sessionId: SessionId) -> Cancellable { AnyCancellable {} }
+    func refresh() -> Cancellable { AnyCancellable {} }
     func delete() -> Result<(), ServicesError> { return .success(()) }
 }
@@ -0,0 +1,290 @@
+// Identity payloads sent over BLE to the peer.
+// Encrypted with a key shared only by the 2 peers (ECDH), so only the peer can read and validate
+// them: others can't link the payload to a public key. A random nonce per payload makes each
+// payload different.
+//
+// Payloads are ephemeral: they carry their creation time and are only accepted within the skew
+// window (see BleConfig), and only once. Apps have to create a new payload (rotate) before the
+// window ends. Without this, a recorded payload could be replayed forever to impersonate a peer.
+//
+// Format: version (1 byte) || nonce (12 bytes) || encrypted (sender fingerprint (32 bytes) ||
+// timestamp (8 bytes, ms since epoch, big endian)) || tag (16 bytes)
+// The version is authenticated as associated data.
+
+use crate::{
+    config::BleConfig,
+    crypto::{self, PrivateKey},
+    errors::CoreError,
+};
+use chrono::{DateTime, Duration, Utc};
+use log::*;
+use std::{collections::HashMap, convert::TryInto};
+
+// 1 identified static payloads, which could be replayed
+const VERSION: u8 = 2;
+const KEY_INFO: &[u8] = b"ploc ble identity v1";
+const FINGERPRINT_LEN: usize = 32;
+const TIMESTAMP_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PayloadValidation {
+    Valid,
+    // Not created by the peer for us, or malformed
+    Invalid,
+    // Created before the skew window
+    Expired,
+    // Created after the skew window, e.g. prepared in advance to be replayed later
+    FutureDated,
+    // Valid, but was already received
+    Replayed,
+}
+
+impl PayloadValidation {
+    pub fn is_valid(&self) -> bool {
+        *self == PayloadValidation::Valid
+    }
+}
+
+pub fn create_identity_payload(
+    private_key: &PrivateKey,
+    peer_public_key_pem: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<u8>, CoreError> {
+    let key = crypto::shared_key(private_key, peer_public_key_pem, KEY_INFO)?;
+    // The peer checks that it was sent by us and not e.g. its own payload, sent back to it.
+    let sender = crypto::key_fingerprint(&crypto::private_key_from_pem(private_key)?.public_key());
+
+    let mut plaintext = sender.to_vec();
+    plaintext.extend(&now.timestamp_millis().to_be_bytes());
+
+    let mut payload = vec![VERSION];
+    payload.extend(crypto::encrypt(&key, &plaintext, &[VERSION])?);
+    Ok(payload)
+}
+
+// Validates the payloads received from peers. Remembers the payloads accepted in the skew window
+// to reject replays, so there should be one validator for all the payloads.
+pub struct IdentityValidator {
+    max_skew: Duration,
+    // Nonce of accepted payloads -> their timestamp (ms), to forget them once expired
+    seen: HashMap<Vec<u8>, i64>,
+}
+
+impl IdentityValidator {
+    pub fn new(config: &BleConfig) -> IdentityValidator {
+        IdentityValidator {
+            max_skew: Duration::milliseconds(config.max_clock_skew_ms as i64),
+            seen: HashMap::new(),
+        }
+    }
+
+    // Whether the payload was created by the peer for us, recently and wasn't received before.
+    // Err only if the keys are invalid: invalid payloads are just not valid.
+    pub fn validate(
+        &mut self,
+        private_key: &PrivateKey,
+        peer_public_key_pem: &str,
+        payload: &[u8],
+        now: DateTime<Utc>,
+    ) -> Result<PayloadValidation, CoreError> {
+        let key = crypto::shared_key(private_key, peer_public_key_pem, KEY_INFO)?;
+        let peer = crypto::key_fingerprint(&crypto::public_key_from_pem(peer_public_key_pem)?);
+
+        let (version, encrypted) = match payload.split_first() {
+            Some((version, encrypted)) => (*version, encrypted),
+            None => return Ok(PayloadValidation::Invalid),
+        };
+        if version != VERSION {
+            warn!("Unsupported identity payload version: {}", version);
+            return Ok(PayloadValidation::Invalid);
+        }
+
+        let plaintext = match crypto::decrypt(&key, encrypted, &[version]) {
+            Some(plaintext) if plaintext.len() == FINGERPRINT_LEN + TIMESTAMP_LEN => plaintext,
+            _ => return Ok(PayloadValidation::Invalid),
+        };
+        let (sender, timestamp) = plaintext.split_at(FINGERPRINT_LEN);
+        if sender != peer {
+            return Ok(PayloadValidation::Invalid);
+        }
+        let timestamp = i64::from_be_bytes(timestamp.try_into().unwrap());
+
+        self.forget_expired(now);
+        let now_ms = now.timestamp_millis();
+        if timestamp < now_ms - self.max_skew.num_milliseconds() {
+            return Ok(PayloadValidation::Expired);
+        }
+        if timestamp > now_ms + self.max_skew.num_milliseconds() {
+            return Ok(PayloadValidation::FutureDated);
+        }
+
+        // The nonce is random and authenticated, so it identifies the payload
+        let nonce = encrypted[..crypto::NONCE_LEN].to_vec();
+        if self.seen.insert(nonce, timestamp).is_some() {
+            return Ok(PayloadValidation::Replayed);
+        }
+        Ok(PayloadValidation::Valid)
+    }
+
+    // Expired payloads are rejected anyway, no need to remember them.
+    fn forget_expired(&mut self, now: DateTime<Utc>) {
+        let min_timestamp = (now - self.max_skew).timestamp_millis();
+        self.seen.retain(|_, timestamp| *timestamp >= min_timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::KeyPair;
+
+    fn key_pair() -> KeyPair {
+        crypto::create_key_pair().unwrap()
+    }
+
+    fn validator() -> IdentityValidator {
+        IdentityValidator::new(&BleConfig {
+            max_clock_skew_ms: 30_000,
+        })
+    }
+
+    fn validate(validator: &mut IdentityValidator, me: &KeyPair, peer: &KeyPair, payload: &[u8]) {
+        assert_eq!(
+            validator
+                .validate(&peer.private, &me.public, payload, Utc::now())
+                .unwrap(),
+            PayloadValidation::Valid
+        );
+    }
+
+    #[test]
+    fn peer_validates_payload() {
+        let (me, peer) = (key_pair(), key_pair());
+
+        let payload = create_identity_payload(&me.private, &peer.public, Utc::now()).unwrap();
+
+        assert_eq!(payload.len(), 1 + 12 + 32 + 8 + 16);
+        validate(&mut validator(), &me, &peer, &payload);
+    }
+
+    #[test]
+    fn payloads_are_not_linkable() {
+        let (me, peer) = (key_pair(), key_pair());
+        let now = Utc::now();
+
+        let payload1 = create_identity_payload(&me.private, &peer.public, now).unwrap();
+        let payload2 = create_identity_payload(&me.private, &peer.public, now).unwrap();
+
+        assert_ne!(payload1, payload2);
+    }
+
+    #[test]
+    fn others_cant_validate_payload() {
+        let (me, peer, other) = (key_pair(), key_pair(), key_pair());
+        let mut validator = validator();
+
+        let payload = create_identity_payload(&me.private, &peer.public, Utc::now()).unwrap();
+
+        let res = validator.validate(&other.private, &me.public, &payload, Utc::now());
+        assert_eq!(res.unwrap(), PayloadValidation::Invalid);
+        // Payload created by someone else, claiming to be me
+        let forged = create_identity_payload(&other.private, &peer.public, Utc::now()).unwrap();
+        let res = validator.validate(&peer.private, &me.public, &forged, Utc::now());
+        assert_eq!(res.unwrap(), PayloadValidation::Invalid);
+    }
+
+    #[test]
+    fn rejects_reflected_payload() {
+        let (me, peer) = (key_pair(), key_pair());
+
+        // My own payload, sent back to me: same shared key, but I'm the sender
+        let payload = create_identity_payload(&me.private, &peer.public, Utc::now()).unwrap();
+
+        let res = validator().validate(&me.private, &peer.public, &payload, Utc::now());
+        assert_eq!(res.unwrap(), PayloadValidation::Invalid);
+    }
+
+    #[test]
+    fn rejects_malformed_payload() {
+        let (me, peer) = (key_pair(), key_pair());
+        let mut validator = validator();
+        let mut payload = create_identity_payload(&me.private, &peer.public, Utc::now()).unwrap();
+
+        for malformed in &[vec![], vec![VERSION]] {
+            let res = validator.validate(&peer.private, &me.public, malformed, Utc::now());
+            assert_eq!(res.unwrap(), PayloadValidation::Invalid);
+        }
+
+        payload[0] = 1;
+        let res = validator.validate(&peer.private, &me.public, &payload, Utc::now());
+        assert_eq!(res.unwrap(), PayloadValidation::Invalid);
+    }
+
+    #[test]
+    fn rejects_replayed_payload() {
+        let (me, peer) = (key_pair(), key_pair());
+        let mut validator = validator();
+        let payload = create_identity_payload(&me.private, &peer.public, Utc::now()).unwrap();
+        validate(&mut validator, &me, &peer, &payload);
+
+        let res = validator.validate(&peer.private, &me.public, &payload, Utc::now());
+
+        assert_eq!(res.unwrap(), PayloadValidation::Replayed);
+        // A new payload is accepted
+        let payload = create_identity_payload(&me.private, &peer.public, Utc::now()).unwrap();
+        validate(&mut validator, &me, &peer, &payload);
+    }
+
+    #[test]
+    fn rejects_expired_payload() {
+        let (me, peer) = (key_pair(), key_pair());
+        let created = Utc::now() - Duration::seconds(31);
+        let payload = create_identity_payload(&me.private, &peer.public, created).unwrap();
+
+        let res = validator().validate(&peer.private, &me.public, &payload, Utc::now());
+
+        assert_eq!(res.unwrap(), PayloadValidation::Expired);
+    }
+
+    #[test]
+    fn rejects_future_dated_payload() {
+        let (me, peer) = (key_pair(), key_pair());
+        let created = Utc::now() + Duration::seconds(31);
+        let payload = create_identity_payload(&me.private, &peer.public, created).unwrap();
+
+        let res = validator().validate(&peer.private, &me.public, &payload, Utc::now());
+
+        assert_eq!(res.unwrap(), PayloadValidation::FutureDated);
+    }
+
+    #[test]
+    fn accepts_payload_within_skew_window() {
+        let (me, peer) = (key_pair(), key_pair());
+        let mut validator = validator();
+        let now = Utc::now();
+
+        for offset in &[-29, 29] {
+            let created = now + Duration::seconds(*offset);
+            let payload = create_identity_payload(&me.private, &peer.public, created).unwrap();
+            let res = validator.validate(&peer.private, &me.public, &payload, now);
+            assert_eq!(res.unwrap(), PayloadValidation::Valid);
+        }
+    }
+
+    #[test]
+    fn forgets_expired_payloads() {
+        let (me, peer) = (key_pair(), key_pair());
+        let mut validator = validator();
+        let now = Utc::now();
+        let payload = create_identity_payload(&me.private, &peer.public, now).unwrap();
+        validator
+            .validate(&peer.private, &me.public, &payload, now)
+            .unwrap();
+
+        let later = now + Duration::seconds(31);
+        let res = validator.validate(&peer.private, &me.public, &payload, later);
+
+        assert_eq!(res.unwrap(), PayloadValidation::Expired);
+        assert!(validator.seen.is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+// The session as the apps know it (structs.swift's Session): our key pair and what we know about
+// the peer. Unlike networking::Session (the backend's view), it's stored locally, serialized to
+// JSON: by the apps or in the secure storage (see globals::save_session).
+
+use crate::{
+    crypto::{self, PrivateKey},
+    errors::CoreError,
+    globals::KeyPair,
+};
+use ploc_common::model_types::PublicKey;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClientSession {
+    pub id: String,
+    pub private_key: PrivateKey,
+    pub public_key: String,
+    // Own peer id (see crypto::peer_id)
+    pub peer_id: String,
+    // Whether we created the session (as opposed to joined it)
+    pub created_by_me: bool,
+    // None until the peer joined the session
+    pub peer: Option<Peer>,
+    // Whether both peers acked the public key of the other
+    pub is_ready: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Peer {
+    pub public_key: String,
+}
+
+impl ClientSession {
+    pub fn new(id: String, key_pair: KeyPair, created_by_me: bool) -> ClientSession {
+        let peer_id = crypto::peer_id(&PublicKey {
+            str: key_pair.public.clone(),
+        });
+        ClientSession {
+            id,
+            private_key: key_pair.private,
+            public_key: key_pair.public,
+            peer_id,
+            created_by_me,
+            peer: None,
+            is_ready: false,
+        }
+    }
+
+    // Consumes the session: the private key isn't Clone, so there's only one copy of it.
+    pub fn with_peer(self, peer: Peer) -> ClientSession {
+        ClientSession {
+            peer: Some(peer),
+            ..self
+        }
+    }
+
+    pub fn with_is_ready(self, is_ready: bool) -> ClientSession {
+        ClientSession { is_ready, ..self }
+    }
+
+    pub fn has_peer(&self) -> bool {
+        self.peer.is_some()
+    }
+
+    // Includes the private key: for storage only
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Couldn't serialize client session")
+    }
+
+    pub fn from_json(json: &str) -> Result<ClientSession, CoreError> {
+        // Not the error's message: it can contain the input, i.e. the private key
+        serde_json::from_str(json).map_err(|e| {
+            CoreError::General(format!(
+                "Invalid client session: {:?} error at {}:{}",
+                e.classify(),
+                e.line(),
+                e.column()
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> ClientSession {
+        ClientSession::new("1".to_owned(), crypto::create_key_pair().unwrap(), true)
+    }
+
+    #[test]
+    fn new_session_has_no_peer() {
+        let session = session();
+
+        assert!(!session.has_peer());
+        assert!(!session.is_ready);
+        assert_eq!(
+            session.peer_id,
+            crypto::peer_id(&PublicKey {
+                str: session.public_key.clone()
+            })
+        );
+    }
+
+    #[test]
+    fn transitions_keep_other_fields() {
+        let session = session();
+        let (id, public_key) = (session.id.clone(), session.public_key.clone());
+        let peer = Peer {
+            public_key: "peer_key".to_owned(),
+        };
+
+        let session = session.with_peer(peer.clone()).with_is_ready(true);
+
+        assert_eq!(session.peer, Some(peer));
+        assert!(session.is_ready);
+        assert_eq!(session.id, id);
+        assert_eq!(session.public_key, public_key);
+        assert!(session.created_by_me);
+    }
+
+    #[test]
+    fn serializes_with_private_key() {
+        let session = session().with_peer(Peer {
+            public_key: "peer_key".to_owned(),
+        });
+
+        let json = session.to_json();
+
+        assert!(json.contains(r#""peer":{"public_key":"peer_key"}"#));
+        assert!(json.contains(&serde_json::to_string(session.private_key.expose()).unwrap()));
+        assert_eq!(ClientSession::from_json(&json).unwrap(), session);
+        // But not in the logs
+        assert!(!format!("{:?}", session).contains("PRIVATE KEY"));
+    }
+}
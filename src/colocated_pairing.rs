@@ -0,0 +1,119 @@
+// Encryption of the public keys exchanged in colocated pairing, with the pairing password
+// (shown by the creator, e.g. as QR code, and read by the joiner).
+// The key is derived from the password with a memory-hard KDF (see crypto::password_key) and a
+// random salt, and the public key is encrypted with an AEAD: a modified payload or a wrong password
+// is detected instead of producing a wrong key.
+//
+// Format: version (1 byte) || salt (16 bytes) || nonce (12 bytes) || encrypted public key || tag (16 bytes)
+// The version and salt are authenticated as associated data.
+
+use crate::{crypto, errors::CoreError};
+
+// Determines the KDF and its parameters, so they can be changed later
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+
+pub fn encrypt_public_key(public_key_pem: &str, password: &str) -> Result<Vec<u8>, CoreError> {
+    if password.is_empty() {
+        return Err(CoreError::General("Password is empty".to_owned()));
+    }
+    // Only valid keys are sent, so the peer can reject anything else
+    crypto::public_key_from_pem(public_key_pem)?;
+
+    let salt = crypto::random_bytes(SALT_LEN);
+    let key = crypto::password_key(password, &salt)?;
+
+    let mut header = vec![VERSION];
+    header.extend(&salt);
+    let encrypted = crypto::encrypt(&key, public_key_pem.as_bytes(), &header)?;
+
+    let mut payload = header;
+    payload.extend(encrypted);
+    Ok(payload)
+}
+
+// Err if the password is wrong, the payload was modified or doesn't contain a public key.
+pub fn decrypt_public_key(payload: &[u8], password: &str) -> Result<String, CoreError> {
+    if payload.len() < 1 + SALT_LEN {
+        return Err(CoreError::General(format!(
+            "Encrypted public key too short: {} bytes",
+            payload.len()
+        )));
+    }
+    let (header, encrypted) = payload.split_at(1 + SALT_LEN);
+    if header[0] != VERSION {
+        return Err(CoreError::General(format!(
+            "Unsupported encrypted public key version: {}",
+            header[0]
+        )));
+    }
+
+    let key = crypto::password_key(password, &header[1..])?;
+    let decrypted = crypto::decrypt(&key, encrypted, header).ok_or_else(|| {
+        CoreError::General("Couldn't decrypt public key: wrong password or modified".to_owned())
+    })?;
+    let public_key_pem = String::from_utf8(decrypted)
+        .map_err(|e| CoreError::General(format!("Decrypted public key isn't utf8: {:?}", e)))?;
+    crypto::public_key_from_pem(&public_key_pem)?;
+    Ok(public_key_pem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decrypts_with_password() {
+        let key_pair = crypto::create_key_pair().unwrap();
+
+        let payload = encrypt_public_key(&key_pair.public, "pairing password").unwrap();
+        let res = decrypt_public_key(&payload, "pairing password");
+
+        assert_eq!(res.unwrap(), key_pair.public);
+    }
+
+    #[test]
+    fn same_key_and_password_give_different_payloads() {
+        let key_pair = crypto::create_key_pair().unwrap();
+
+        let payload1 = encrypt_public_key(&key_pair.public, "123").unwrap();
+        let payload2 = encrypt_public_key(&key_pair.public, "123").unwrap();
+
+        // Different salt and nonce
+        assert_ne!(payload1[1..1 + SALT_LEN], payload2[1..1 + SALT_LEN]);
+        assert_ne!(payload1, payload2);
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        let key_pair = crypto::create_key_pair().unwrap();
+        let payload = encrypt_public_key(&key_pair.public, "123").unwrap();
+
+        assert!(decrypt_public_key(&payload, "124").is_err());
+    }
+
+    #[test]
+    fn rejects_modified_payload() {
+        let key_pair = crypto::create_key_pair().unwrap();
+        let payload = encrypt_public_key(&key_pair.public, "123").unwrap();
+
+        // Salt: derives a different key. Version: authenticated, besides being checked.
+        for index in &[1, payload.len() - 1] {
+            let mut modified = payload.clone();
+            modified[*index] ^= 1;
+            assert!(decrypt_public_key(&modified, "123").is_err());
+        }
+        let mut modified = payload;
+        modified[0] = 2;
+        assert!(decrypt_public_key(&modified, "123").is_err());
+        assert!(decrypt_public_key(&[VERSION], "123").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let key_pair = crypto::create_key_pair().unwrap();
+
+        assert!(encrypt_public_key(&key_pair.public, "").is_err());
+        assert!(encrypt_public_key("not a key", "123").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use backoff::ExponentialBackoff;
+use serde::Deserialize;
+use std::time::Duration;
+
+// Passed by the apps as JSON when bootstrapping the core.
+// Fields are optional (except the base url), missing ones fall back to the defaults.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CoreConfig {
+    pub base_url: String,
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub events: EventsConfig,
+    #[serde(default)]
+    pub ble: BleConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub initial_interval_ms: u64,
+    pub max_interval_ms: u64,
+    pub multiplier: f64,
+    // Total time we keep retrying a request. 0 disables retries.
+    pub max_elapsed_time_ms: u64,
+}
+
+// Session events stream (see session_events.rs)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    pub reconnect_interval_ms: u64,
+    // Consecutive failed connections before falling back to polling
+    pub max_reconnect_attempts: u32,
+    // While polling, the stream is retried with exponential backoff (from the reconnect interval)
+    // up to this interval
+    pub max_reconnect_interval_ms: u64,
+    // Reconnect if nothing (not even a keep alive) was received for this long
+    pub idle_timeout_ms: u64,
+    pub polling_interval_ms: u64,
+}
+
+// BLE identity payloads (see ble_payload.rs)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BleConfig {
+    // Payloads are accepted if created at most this long before or after now.
+    // Covers the clock difference of the devices and the time until the payload is received.
+    pub max_clock_skew_ms: u64,
+}
+
+fn default_connect_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_request_timeout_ms() -> u64 {
+    10_000
+}
+
+// For tests: there's no default base url, the apps always pass it
+#[cfg(test)]
+impl Default for CoreConfig {
+    fn default() -> Self {
+        CoreConfig {
+            base_url: "http://127.0.0.1:8000/".to_owned(),
+            connect_timeout_ms: default_connect_timeout_ms(),
+            request_timeout_ms: default_request_timeout_ms(),
+            retry: RetryConfig::default(),
+            events: EventsConfig::default(),
+            ble: BleConfig::default(),
+        }
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            initial_interval_ms: 500,
+            max_interval_ms: 2_000,
+            multiplier: 1.5,
+            max_elapsed_time_ms: 5_000,
+        }
+    }
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        EventsConfig {
+            reconnect_interval_ms: 1_000,
+            max_reconnect_attempts: 3,
+            max_reconnect_interval_ms: 60_000,
+            idle_timeout_ms: 60_000,
+            polling_interval_ms: 3_000,
+        }
+    }
+}
+
+impl Default for BleConfig {
+    fn default() -> Self {
+        BleConfig {
+            max_clock_skew_ms: 30_000,
+        }
+    }
+}
+
+impl CoreConfig {
+    pub fn from_json(json: &str) -> Result<CoreConfig, String> {
+        let config: CoreConfig =
+            serde_json::from_str(json).map_err(|e| format!("Invalid config JSON: {:?}", e))?;
+        config.validated()
+    }
+
+    fn validated(mut self) -> Result<CoreConfig, String> {
+        if !(self.base_url.starts_with("http://") || self.base_url.starts_with("https://")) {
+            return Err(format!("Invalid base url: {}", self.base_url));
+        }
+        if !self.base_url.ends_with('/') {
+            self.base_url.push('/');
+        }
+        Ok(self)
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms)
+    }
+}
+
+impl RetryConfig {
+    pub fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoff {
+            initial_interval: Duration::from_millis(self.initial_interval_ms),
+            current_interval: Duration::from_millis(self.initial_interval_ms),
+            max_interval: Duration::from_millis(self.max_interval_ms),
+            multiplier: self.multiplier,
+            max_elapsed_time: Some(Duration::from_millis(self.max_elapsed_time_ms)),
+            ..ExponentialBackoff::default()
+        }
+    }
+}
+
+impl EventsConfig {
+    pub fn reconnect_interval(&self) -> Duration {
+        Duration::from_millis(self.reconnect_interval_ms)
+    }
+
+    pub fn max_reconnect_interval(&self) -> Duration {
+        Duration::from_millis(self.max_reconnect_interval_ms)
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.idle_timeout_ms)
+    }
+
+    pub fn polling_interval(&self) -> Duration {
+        Duration::from_millis(self.polling_interval_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_config_with_defaults() {
+        let res = CoreConfig::from_json(r#"{"base_url": "https://ploc.example.com"}"#);
+
+        assert!(res.is_ok());
+        let config = res.unwrap();
+        assert_eq!(config.base_url, "https://ploc.example.com/");
+        assert_eq!(config.url("key"), "https://ploc.example.com/key");
+        assert_eq!(config.request_timeout_ms, 10_000);
+        assert_eq!(config.retry, RetryConfig::default());
+    }
+
+    #[test]
+    fn parses_retry_config() {
+        let res = CoreConfig::from_json(
+            r#"{"base_url": "http://10.0.2.2:8000/", "retry": {"max_elapsed_time_ms": 0}}"#,
+        );
+
+        assert!(res.is_ok());
+        let retry = res.unwrap().retry;
+        assert_eq!(retry.max_elapsed_time_ms, 0);
+        assert_eq!(retry.initial_interval_ms, 500);
+    }
+
+    #[test]
+    fn rejects_config_without_base_url() {
+        let res = CoreConfig::from_json(r#"{"request_timeout_ms": 1000}"#);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_base_url() {
+        let res = CoreConfig::from_json(r#"{"base_url": "ploc.example.com"}"#);
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    config::CoreConfig,
+    crypto::{self, PrivateKey},
+    errors::CoreError,
+};
+use chrono::Utc;
+use ploc_common::networking_types::{
+    AckRequestParams, AckSessionResult, JoinSessionResult, ParticipantsRequestParams,
+    ParticipantsResult, PeerDeleteSesionParams, SessionKeyRequestParams,
+};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Method,
+};
+use serde::{de::DeserializeOwned, de::IgnoredAny, Serialize};
+use std::fmt::Debug;
+
+// Describes a backend endpoint. Requests are sent with RemoteSessionApiImpl::call
+// (or the async counterpart).
+pub trait Endpoint {
+    type Request: Serialize + Debug + Sync;
+    // Payload of the response envelope
+    type Response: DeserializeOwned + Debug + Send;
+
+    const METHOD: Method;
+    const PATH: &'static str;
+    // Mutates state: requests are signed with the session private key (see prepare)
+    const SIGNED: bool = false;
+}
+
+// Unix time (seconds) the request was signed at, so the backend can reject old (replayed) requests.
+// Retries send the same headers: the backend's window has to cover the retry policy.
+pub const TIMESTAMP_HEADER: &str = "x-ploc-timestamp";
+// Base64 of the raw signature (see crypto::sign) of signed_message
+pub const SIGNATURE_HEADER: &str = "x-ploc-signature";
+
+// Everything needed to send a request, independently of the http client.
+// Cross-cutting concerns (headers etc.) are added here, for both the blocking and async apis.
+#[derive(Debug)]
+pub struct PreparedRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+// private_key is required for signed endpoints. The backend verifies the signature against the
+// key posted when joining the session (for the join itself, against the posted key).
+pub fn prepare<E: Endpoint>(
+    config: &CoreConfig,
+    params: &E::Request,
+    private_key: Option<&PrivateKey>,
+) -> Result<PreparedRequest, CoreError> {
+    let body = serde_json::to_string(params)
+        .map_err(|e| CoreError::General(format!("Couldn't serialize params: {:?}", e)))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    if E::SIGNED {
+        let private_key = private_key.ok_or_else(|| {
+            CoreError::General(format!("Missing private key to sign: {}", E::PATH))
+        })?;
+        let timestamp = Utc::now().timestamp().to_string();
+        let message = signed_message(&E::METHOD, E::PATH, &timestamp, &body);
+        let signature = crypto::sign(private_key, message.as_bytes())?;
+        insert_header(&mut headers, TIMESTAMP_HEADER, &timestamp)?;
+        insert_header(&mut headers, SIGNATURE_HEADER, &base64::encode(signature))?;
+    }
+
+    Ok(PreparedRequest {
+        method: E::METHOD,
+        url: config.url(E::PATH),
+        headers,
+        body,
+    })
+}
+
+// Method and path are included, so a signature can't be reused for another endpoint.
+pub fn signed_message(method: &Method, path: &str, timestamp: &str, body: &str) -> String {
+    format!("{}\n/{}\n{}\n{}", method, path, timestamp, body)
+}
+
+fn insert_header(
+    headers: &mut HeaderMap,
+    name: &'static str,
+    value: &str,
+) -> Result<(), CoreError> {
+    let value = HeaderValue::from_str(value)
+        .map_err(|e| CoreError::General(format!("Invalid header value: {:?}", e)))?;
+    headers.insert(HeaderName::from_static(name), value);
+    Ok(())
+}
+
+pub struct JoinSession;
+impl Endpoint for JoinSession {
+    type Request = SessionKeyRequestParams;
+    type Response = JoinSessionResult;
+    const METHOD: Method = Method::POST;
+    const PATH: &'static str = "key";
+    const SIGNED: bool = true;
+}
+
+pub struct Ack;
+impl Endpoint for Ack {
+    type Request = AckRequestParams;
+    type Response = AckSessionResult;
+    const METHOD: Method = Method::POST;
+    const PATH: &'static str = "ready";
+    const SIGNED: bool = true;
+}
+
+pub struct Participants;
+impl Endpoint for Participants {
+    type Request = ParticipantsRequestParams;
+    type Response = ParticipantsResult;
+    const METHOD: Method = Method::POST;
+    const PATH: &'static str = "part";
+}
+
+pub struct Delete;
+impl Endpoint for Delete {
+    type Request = PeerDeleteSesionParams;
+    // Success doesn't have a payload
+    type Response = IgnoredAny;
+    const METHOD: Method = Method::POST;
+    const PATH: &'static str = "del";
+    const SIGNED: bool = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header<'a>(request: &'a PreparedRequest, name: &str) -> &'a str {
+        request.headers.get(name).unwrap().to_str().unwrap()
+    }
+
+    #[test]
+    fn signs_mutating_requests() {
+        let key_pair = crypto::create_key_pair().unwrap();
+        let params = PeerDeleteSesionParams {
+            peer_id: "peer".to_owned(),
+        };
+
+        let request =
+            prepare::<Delete>(&CoreConfig::default(), &params, Some(&key_pair.private)).unwrap();
+
+        let timestamp = header(&request, TIMESTAMP_HEADER);
+        assert!((Utc::now().timestamp() - timestamp.parse::<i64>().unwrap()).abs() < 5);
+        let signature = base64::decode(header(&request, SIGNATURE_HEADER)).unwrap();
+        let message = signed_message(&Method::POST, "del", timestamp, &request.body);
+        assert!(crypto::verify(&key_pair.public, message.as_bytes(), &signature).unwrap());
+        // Bound to the endpoint
+        let message = signed_message(&Method::POST, "ready", timestamp, &request.body);
+        assert!(!crypto::verify(&key_pair.public, message.as_bytes(), &signature).unwrap());
+    }
+
+    #[test]
+    fn requires_private_key_for_mutating_requests() {
+        let params = PeerDeleteSesionParams {
+            peer_id: "peer".to_owned(),
+        };
+
+        let res = prepare::<Delete>(&CoreConfig::default(), &params, None);
+
+        assert!(matches!(res, Err(CoreError::General(_))));
+    }
+
+    #[test]
+    fn does_not_sign_reads() {
+        let params = ParticipantsRequestParams {
+            session_id: "1".to_owned(),
+        };
+
+        let request = prepare::<Participants>(&CoreConfig::default(), &params, None).unwrap();
+
+        assert!(request.headers.get(SIGNATURE_HEADER).is_none());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::session_state::{SessionOperation, SessionState};
+use ploc_common::errors::NetworkingError;
+use std::fmt;
+
+// Core specific errors. The apps receive them as status codes (see status_code()).
+// The server error codes (session not found, full, already acked) have their own variants here and
+// not in ploc_common's error types, which are shared with the backend. The iOS app maps the status
+// codes to distinct ServicesError cases (see Core.swift).
+#[derive(Debug)]
+pub enum CoreError {
+    General(String),
+    // Transport errors and server errors without a known error code
+    Networking(NetworkingError),
+    // Transient networking errors, retried until the retry policy gave up
+    RetriesExhausted {
+        attempts: u32,
+        last_error: NetworkingError,
+    },
+    SessionNotFound,
+    SessionFull,
+    AlreadyAcked,
+    // The operation was cancelled by the app
+    Cancelled,
+    // The short authentication strings of the peers differ: a key was substituted
+    AuthStringMismatch,
+    // The operation isn't valid in the session's current state (see session_state.rs)
+    InvalidTransition {
+        from: SessionState,
+        operation: SessionOperation,
+    },
+    // There's already an active session (see SessionState::is_active)
+    SessionAlreadyActive {
+        session_id: String,
+    },
+    // The id of the session to start is used by another backend session
+    SessionIdTaken,
+    // Malformed session id (see session_id.rs). Only the length: the id is untrusted input.
+    InvalidSessionId {
+        len: usize,
+    },
+}
+
+impl CoreError {
+    // Status passed to the apps in the FFI results. 1 is reserved for success.
+    pub fn status_code(&self) -> i32 {
+        match self {
+            CoreError::General(_) => 0,
+            CoreError::Networking(_) | CoreError::RetriesExhausted { .. } => 2,
+            CoreError::SessionNotFound => 3,
+            CoreError::SessionFull => 4,
+            CoreError::AlreadyAcked => 5,
+            CoreError::Cancelled => 6,
+            CoreError::AuthStringMismatch => 7,
+            CoreError::InvalidTransition { .. } => 8,
+            CoreError::SessionAlreadyActive { .. } => 9,
+            CoreError::SessionIdTaken => 10,
+            CoreError::InvalidSessionId { .. } => 11,
+        }
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoreError::General(message) => write!(f, "{}", message),
+            CoreError::Networking(e) => write!(f, "Networking error: {:?}", e),
+            CoreError::RetriesExhausted {
+                attempts,
+                last_error,
+            } => write!(
+                f,
+                "Networking error after {} attempts, last error: {:?}",
+                attempts, last_error
+            ),
+            CoreError::SessionNotFound => write!(f, "Session not found"),
+            CoreError::SessionFull => write!(f, "Session is full"),
+            CoreError::AlreadyAcked => write!(f, "Session was already acked"),
+            CoreError::Cancelled => write!(f, "Cancelled"),
+            CoreError::AuthStringMismatch => write!(
+                f,
+                "Short authentication string doesn't match, the peer's key may have been substituted"
+            ),
+            CoreError::InvalidTransition { from, operation } => write!(
+                f,
+                "Invalid session operation: {:?} in state: {:?}",
+                operation, from
+            ),
+            CoreError::SessionAlreadyActive { session_id } => {
+                write!(f, "There's already an active session: {}", session_id)
+            }
+            CoreError::SessionIdTaken => write!(f, "Session id is already used"),
+            CoreError::InvalidSessionId { len } => {
+                write!(f, "Invalid session id, length: {}", len)
+            }
+        }
+    }
+}
+
+impl From<NetworkingError> for CoreError {
+    fn from(error: NetworkingError) -> Self {
+        CoreError::Networking(error)
+    }
+}
+
+impl From<reqwest::Error> for CoreError {
+    fn from(error: reqwest::Error) -> Self {
+        CoreError::Networking(NetworkingError::from(error))
+    }
+}
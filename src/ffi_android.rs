@@ -4,11 +4,34 @@ use std::{
     thread,
 };
 
+use crate::client_session::{ClientSession, Peer};
+use crate::config::CoreConfig;
+use crate::crypto::PrivateKey;
+use crate::errors::CoreError;
+use crate::ffi_common::{
+    register_async_result_sender, send_async_result, session_state_str, AsyncResult,
+};
+use crate::globals::bootstrap;
+use crate::globals::generate_session_id;
+use crate::globals::{
+    ack_async, cancel, delete_async, join_session_with_id_async, participants_async,
+    refresh_session_async, start_session_async, subscribe_session_events,
+};
+use crate::globals::{
+    check_short_auth_string, convert_public_key, create_client_session, create_identity_payload,
+    create_key_pair, decrypt_public_key, encrypt_public_key, peer_id, short_auth_string, sign,
+    validate_identity_payload, verify,
+};
+use crate::globals::{clear_session, load_session, save_session, set_secure_storage};
+use crate::globals::{end_meeting, session_state, start_meeting};
+use crate::key_format::KeyFormat;
+use crate::secure_storage::SecureStorage;
+use crate::session_refresh::RefreshSessionParams;
 use jni::objects::{GlobalRef, JClass, JObject, JString, JValue};
-use jni::sys::{jint, jobject, jstring};
+use jni::sys::{jboolean, jbyteArray, jint, jlong, jobject, jstring, JNI_FALSE, JNI_TRUE};
 use jni::JNIEnv;
 use jni::JavaVM;
-use log::info;
+use log::{error, info};
 
 #[no_mangle]
 pub unsafe extern "system" fn Java_com_match_android_JniApi_initLogger(_: JNIEnv, _: JClass) {
@@ -27,6 +50,355 @@ pub unsafe extern "system" fn Java_com_match_android_JniApi_initLogger(_: JNIEnv
     info!("init log system - done");
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_bootstrap(
+    env: JNIEnv,
+    _: JClass,
+    config_json: JString,
+) -> jint {
+    let config_json_str: String = env
+        .get_string(config_json)
+        .expect("Couldn't create rust string")
+        .into();
+
+    let config = match CoreConfig::from_json(&config_json_str) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Invalid core config: {}", e);
+            return 0;
+        }
+    };
+
+    match bootstrap(config) {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error bootstrapping: {:?}", e);
+            0
+        }
+    }
+}
+
+// Returns {"private_key": "...", "public_key": "..."} (PEM), empty string on error
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_createKeyPair(
+    env: JNIEnv,
+    _: JClass,
+) -> jstring {
+    let json = match create_key_pair() {
+        Ok(key_pair) => serde_json::json!({
+            "private_key": key_pair.private.expose(),
+            "public_key": key_pair.public,
+        })
+        .to_string(),
+        Err(e) => {
+            error!("Error creating key pair: {:?}", e);
+            "".to_owned()
+        }
+    };
+
+    env.new_string(json)
+        .expect("Couldn't create java string")
+        .into_inner()
+}
+
+// Id for a new session, 32 hex characters (see session_id.rs)
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_generateSessionId(
+    env: JNIEnv,
+    _: JClass,
+) -> jstring {
+    env.new_string(generate_session_id())
+        .expect("Couldn't create java string")
+        .into_inner()
+}
+
+// Hex SHA-256 of the PEM public key
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_peerId(
+    env: JNIEnv,
+    _: JClass,
+    public_key: JString,
+) -> jstring {
+    let public_key_str = to_rust_string(&env, public_key);
+    env.new_string(peer_id(public_key_str))
+        .expect("Couldn't create java string")
+        .into_inner()
+}
+
+// Returns the raw signature (r || s), null on error
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_sign(
+    env: JNIEnv,
+    _: JClass,
+    private_key: JString,
+    payload: jbyteArray,
+) -> jbyteArray {
+    let private_key = PrivateKey::from(to_rust_string(&env, private_key));
+    let payload = env
+        .convert_byte_array(payload)
+        .expect("Couldn't convert payload");
+
+    match sign(&private_key, &payload) {
+        Ok(signature) => env
+            .byte_array_from_slice(&signature)
+            .expect("Couldn't create java byte array"),
+        Err(e) => {
+            error!("Error signing: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// Returns false also if the public key is invalid (logged)
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_verify(
+    env: JNIEnv,
+    _: JClass,
+    public_key: JString,
+    payload: jbyteArray,
+    signature: jbyteArray,
+) -> jboolean {
+    let public_key_str = to_rust_string(&env, public_key);
+    let payload = env
+        .convert_byte_array(payload)
+        .expect("Couldn't convert payload");
+    let signature = env
+        .convert_byte_array(signature)
+        .expect("Couldn't convert signature");
+
+    match verify(&public_key_str, &payload, &signature) {
+        Ok(true) => JNI_TRUE,
+        Ok(false) => JNI_FALSE,
+        Err(e) => {
+            error!("Error verifying signature: {:?}", e);
+            JNI_FALSE
+        }
+    }
+}
+
+// Payload to send to the peer over BLE, null on error
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_createIdentityPayload(
+    env: JNIEnv,
+    _: JClass,
+    private_key: JString,
+    peer_public_key: JString,
+) -> jbyteArray {
+    let private_key = PrivateKey::from(to_rust_string(&env, private_key));
+    let peer_public_key_str = to_rust_string(&env, peer_public_key);
+
+    match create_identity_payload(&private_key, &peer_public_key_str) {
+        Ok(payload) => env
+            .byte_array_from_slice(&payload)
+            .expect("Couldn't create java byte array"),
+        Err(e) => {
+            error!("Error creating identity payload: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// Returns false also if the keys are invalid (logged)
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_validateIdentityPayload(
+    env: JNIEnv,
+    _: JClass,
+    private_key: JString,
+    peer_public_key: JString,
+    payload: jbyteArray,
+) -> jboolean {
+    let private_key = PrivateKey::from(to_rust_string(&env, private_key));
+    let peer_public_key_str = to_rust_string(&env, peer_public_key);
+    let payload = env
+        .convert_byte_array(payload)
+        .expect("Couldn't convert payload");
+
+    match validate_identity_payload(&private_key, &peer_public_key_str, &payload) {
+        Ok(true) => JNI_TRUE,
+        Ok(false) => JNI_FALSE,
+        Err(e) => {
+            error!("Error validating identity payload: {:?}", e);
+            JNI_FALSE
+        }
+    }
+}
+
+// Colocated pairing: our public key, encrypted with the pairing password. Null on error.
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_encryptPublicKey(
+    env: JNIEnv,
+    _: JClass,
+    public_key: JString,
+    password: JString,
+) -> jbyteArray {
+    let public_key_str = to_rust_string(&env, public_key);
+    let password_str = to_rust_string(&env, password);
+
+    match encrypt_public_key(&public_key_str, &password_str) {
+        Ok(encrypted) => env
+            .byte_array_from_slice(&encrypted)
+            .expect("Couldn't create java byte array"),
+        Err(e) => {
+            error!("Error encrypting public key: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// PEM public key. Null if the password is wrong or the data was modified.
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_decryptPublicKey(
+    env: JNIEnv,
+    _: JClass,
+    encrypted_key: jbyteArray,
+    password: JString,
+) -> jstring {
+    let encrypted_key = env
+        .convert_byte_array(encrypted_key)
+        .expect("Couldn't convert encrypted key");
+    let password_str = to_rust_string(&env, password);
+
+    match decrypt_public_key(&encrypted_key, &password_str) {
+        Ok(public_key) => env
+            .new_string(public_key)
+            .expect("Couldn't create java string")
+            .into_inner(),
+        Err(e) => {
+            error!("Error decrypting public key: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// from / to: 0 -> PEM, 1 -> DER, 2 -> compressed (see KeyFormat).
+// Returns the PEM, or base64url for the binary formats, null on error
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_convertPublicKey(
+    env: JNIEnv,
+    _: JClass,
+    key: JString,
+    from: jint,
+    to: jint,
+) -> jstring {
+    let key_str = to_rust_string(&env, key);
+    let formats = KeyFormat::from_code(from).zip(KeyFormat::from_code(to));
+    let res = formats
+        .ok_or_else(|| CoreError::General(format!("Invalid key formats: {}, {}", from, to)))
+        .and_then(|(from, to)| convert_public_key(&key_str, from, to));
+
+    match res {
+        Ok(key) => env
+            .new_string(key)
+            .expect("Couldn't create java string")
+            .into_inner(),
+        Err(e) => {
+            error!("Error converting public key: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// JSON: {"digits": "012345", "emoji": ["🐶", ...]}, empty on error (a key is invalid)
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_shortAuthString(
+    env: JNIEnv,
+    _: JClass,
+    own_key: JString,
+    peer_key: JString,
+) -> jstring {
+    let own_key_str = to_rust_string(&env, own_key);
+    let peer_key_str = to_rust_string(&env, peer_key);
+
+    let json = match short_auth_string(&own_key_str, &peer_key_str) {
+        Ok(sas) => serde_json::to_string(&sas).expect("Couldn't serialize short auth string"),
+        Err(e) => {
+            error!("Error creating short authentication string: {:?}", e);
+            "".to_owned()
+        }
+    };
+
+    env.new_string(json)
+        .expect("Couldn't create java string")
+        .into_inner()
+}
+
+// 1 if the peer's code (digits or emoji) matches, 7 if not, 0 if a key is invalid, otherwise
+// error status
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_checkShortAuthString(
+    env: JNIEnv,
+    _: JClass,
+    own_key: JString,
+    peer_key: JString,
+    peer_code: JString,
+) -> jint {
+    let own_key_str = to_rust_string(&env, own_key);
+    let peer_key_str = to_rust_string(&env, peer_key);
+    let peer_code_str = to_rust_string(&env, peer_code);
+
+    match check_short_auth_string(&own_key_str, &peer_key_str, &peer_code_str) {
+        Ok(()) => 1,
+        Err(e) => {
+            error!("Short authentication string check failed: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+// Local session with a new key pair, for the app to store (see client_session.rs).
+// The client session functions return its JSON (including the private key), empty on error.
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_createClientSession(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+    created_by_me: jboolean,
+) -> jstring {
+    let session_id_str = to_rust_string(&env, session_id);
+    let res = create_client_session(session_id_str, created_by_me == JNI_TRUE);
+    to_client_session_jstring(&env, res)
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_clientSessionWithPeer(
+    env: JNIEnv,
+    _: JClass,
+    session_json: JString,
+    peer_public_key: JString,
+) -> jstring {
+    let peer = Peer {
+        public_key: to_rust_string(&env, peer_public_key),
+    };
+    let res = ClientSession::from_json(&to_rust_string(&env, session_json))
+        .map(|session| session.with_peer(peer));
+    to_client_session_jstring(&env, res)
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_clientSessionWithIsReady(
+    env: JNIEnv,
+    _: JClass,
+    session_json: JString,
+    is_ready: jboolean,
+) -> jstring {
+    let res = ClientSession::from_json(&to_rust_string(&env, session_json))
+        .map(|session| session.with_is_ready(is_ready == JNI_TRUE));
+    to_client_session_jstring(&env, res)
+}
+
+fn to_client_session_jstring(env: &JNIEnv, res: Result<ClientSession, CoreError>) -> jstring {
+    let json = match res {
+        Ok(session) => session.to_json(),
+        Err(e) => {
+            error!("Client session error: {:?}", e);
+            "".to_owned()
+        }
+    };
+    env.new_string(json)
+        .expect("Couldn't create java string")
+        .into_inner()
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_com_match_android_JniApi_greet(
     env: JNIEnv,
@@ -183,3 +555,418 @@ impl MyCallback for MyCallbackImpl {
         .expect("Couldn't call callback");
     }
 }
+
+// Async operations return a request id immediately, the result is delivered later
+// to the callback registered with registerAsyncResultCallback, with the same request id.
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_createSessionAsync(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+    key: JString,
+    private_key: JString,
+    reuse_active: jboolean,
+) -> jlong {
+    let session_id_str = to_rust_string(&env, session_id);
+    let key_str = to_rust_string(&env, key);
+    let private_key = PrivateKey::from(to_rust_string(&env, private_key));
+    start_session_async(
+        session_id_str,
+        key_str,
+        private_key,
+        reuse_active == JNI_TRUE,
+        |request_id, res| send_async_result(AsyncResult::from_session(request_id, res)),
+    ) as jlong
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_joinSessionAsync(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+    key: JString,
+    private_key: JString,
+) -> jlong {
+    let session_id_str = to_rust_string(&env, session_id);
+    let key_str = to_rust_string(&env, key);
+    let private_key = PrivateKey::from(to_rust_string(&env, private_key));
+    join_session_with_id_async(session_id_str, key_str, private_key, |request_id, res| {
+        send_async_result(AsyncResult::from_session(request_id, res))
+    }) as jlong
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_ackAsync(
+    env: JNIEnv,
+    _: JClass,
+    public_key: JString,
+    stored_participants: jint,
+    private_key: JString,
+) -> jlong {
+    let public_key_str = to_rust_string(&env, public_key);
+    let private_key = PrivateKey::from(to_rust_string(&env, private_key));
+    ack_async(
+        public_key_str,
+        stored_participants,
+        private_key,
+        |request_id, res| send_async_result(AsyncResult::from_ack(request_id, res)),
+    ) as jlong
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_participantsAsync(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+) -> jlong {
+    let session_id_str = to_rust_string(&env, session_id);
+    participants_async(session_id_str, |request_id, res| {
+        send_async_result(AsyncResult::from_session(request_id, res))
+    }) as jlong
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_deleteAsync(
+    env: JNIEnv,
+    _: JClass,
+    public_key: JString,
+    private_key: JString,
+) -> jlong {
+    let public_key_str = to_rust_string(&env, public_key);
+    let private_key = PrivateKey::from(to_rust_string(&env, private_key));
+    delete_async(public_key_str, private_key, |request_id, res| {
+        send_async_result(AsyncResult::from_delete(request_id, res))
+    }) as jlong
+}
+
+// peer_key: the stored peer key, null if there's none yet
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_refreshSessionAsync(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+    own_key: JString,
+    own_private_key: JString,
+    peer_key: JString,
+) -> jlong {
+    let params = RefreshSessionParams {
+        session_id: to_rust_string(&env, session_id),
+        own_key: to_rust_string(&env, own_key),
+        own_private_key: PrivateKey::from(to_rust_string(&env, own_private_key)),
+        peer_key: if peer_key.is_null() {
+            None
+        } else {
+            Some(to_rust_string(&env, peer_key))
+        },
+    };
+    refresh_session_async(params, |request_id, res| {
+        send_async_result(AsyncResult::from_refresh(request_id, res))
+    }) as jlong
+}
+
+// Session events are delivered to the async result callback with the returned request id
+// (see JniApi.subscribeSessionEvents). Unsubscribe with cancel.
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_subscribeSessionEvents(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+    own_key: JString,
+) -> jlong {
+    let session_id_str = to_rust_string(&env, session_id);
+    let own_key_str = to_rust_string(&env, own_key);
+    subscribe_session_events(
+        session_id_str,
+        own_key_str,
+        |request_id, event| send_async_result(AsyncResult::from_event(request_id, event)),
+        |request_id, res| send_async_result(AsyncResult::from_subscription_end(request_id, res)),
+    ) as jlong
+}
+
+// e.g. "waiting_for_peer" (see session_state.rs), empty if the session isn't tracked
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_sessionState(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+) -> jstring {
+    let session_id_str = to_rust_string(&env, session_id);
+    env.new_string(session_state_str(session_state(&session_id_str)))
+        .expect("Couldn't create java string")
+        .into_inner()
+}
+
+// The meeting follows the pairing (state ready).
+// Returns 1 on success, 8 if not valid in the session's state, otherwise error status.
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_startMeeting(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+) -> jint {
+    let session_id_str = to_rust_string(&env, session_id);
+    match start_meeting(&session_id_str) {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error starting meeting: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_endMeeting(
+    env: JNIEnv,
+    _: JClass,
+    session_id: JString,
+) -> jint {
+    let session_id_str = to_rust_string(&env, session_id);
+    match end_meeting(&session_id_str) {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error ending meeting: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+// Returns 1 if registered, 0 if there was already a storage registered
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_registerSecureStorage(
+    env: JNIEnv,
+    _: JClass,
+    storage: jobject,
+) -> jint {
+    let storage = SecureStorageImpl {
+        java_vm: env.get_java_vm().unwrap(),
+        storage: env.new_global_ref(storage).unwrap(),
+    };
+    if set_secure_storage(Box::new(storage)) {
+        1
+    } else {
+        0
+    }
+}
+
+// Stores the client session (JSON, see createClientSession) in the registered secure storage,
+// replacing the stored one. Returns 1 on success, otherwise error status.
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_saveSession(
+    env: JNIEnv,
+    _: JClass,
+    session_json: JString,
+) -> jint {
+    let res = ClientSession::from_json(&to_rust_string(&env, session_json))
+        .and_then(|session| save_session(&session));
+    match res {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error saving session: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+// LoadSessionResult: status 1 -> success, otherwise error status. The JSON is empty if there's no
+// stored session or on error.
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_loadSession(
+    env: JNIEnv,
+    _: JClass,
+) -> jobject {
+    let (status, json) = match load_session() {
+        Ok(Some(session)) => (1, session.to_json()),
+        Ok(None) => (1, "".to_owned()),
+        Err(e) => {
+            error!("Error loading session: {:?}", e);
+            (e.status_code(), "".to_owned())
+        }
+    };
+    let json = env.new_string(json).expect("Couldn't create java string");
+    env.new_object(
+        "com/match/android/LoadSessionResult",
+        "(ILjava/lang/String;)V",
+        &[JValue::from(status), JValue::from(JObject::from(json))],
+    )
+    .expect("Couldn't create load session result")
+    .into_inner()
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_clearSession(
+    _: JNIEnv,
+    _: JClass,
+) -> jint {
+    match clear_session() {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error clearing session: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+// Returns 1 if cancelled, 0 if there's no operation in progress with this id
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_cancel(
+    _: JNIEnv,
+    _: JClass,
+    request_id: jlong,
+) -> jint {
+    if cancel(request_id as u64) {
+        1
+    } else {
+        0
+    }
+}
+
+// Returns 1 if registered, 0 if there was already a callback registered
+#[no_mangle]
+pub unsafe extern "system" fn Java_com_match_android_JniApi_registerAsyncResultCallback(
+    env: JNIEnv,
+    _: JClass,
+    callback: jobject,
+) -> jint {
+    let callback = AsyncResultCallbackImpl {
+        java_vm: env.get_java_vm().unwrap(),
+        callback: env.new_global_ref(callback).unwrap(),
+    };
+
+    let (tx, rx): (Sender<AsyncResult>, Receiver<AsyncResult>) = mpsc::channel();
+
+    if !register_async_result_sender(tx) {
+        error!("Async result callback already registered");
+        return 0;
+    }
+
+    // Thread waits for results and calls the callback
+    thread::spawn(move || {
+        for result in rx.iter() {
+            callback.call(result)
+        }
+    });
+    1
+}
+
+// See MyCallbackImpl
+struct AsyncResultCallbackImpl {
+    callback: GlobalRef,
+    java_vm: JavaVM,
+}
+
+impl AsyncResultCallbackImpl {
+    fn call(&self, result: AsyncResult) {
+        let env = self.java_vm.attach_current_thread().unwrap();
+
+        let json = env
+            .new_string(result.result_json)
+            .expect("Couldn't create java string!");
+
+        env.call_method(
+            self.callback.as_obj(),
+            "onResult",
+            "(JILjava/lang/String;)V",
+            &[
+                JValue::Long(result.request_id as jlong),
+                JValue::Int(result.status),
+                JValue::from(JObject::from(json)),
+            ],
+        )
+        .expect("Couldn't call async result callback");
+    }
+}
+
+// The app's CoreSecureStorage (see secure_storage.rs and MyCallbackImpl).
+// Called on the thread of the core function using the storage.
+struct SecureStorageImpl {
+    storage: GlobalRef,
+    java_vm: JavaVM,
+}
+
+impl SecureStorage for SecureStorageImpl {
+    fn get(&self, key: &str) -> Result<Option<String>, CoreError> {
+        let env = self.java_vm.attach_current_thread().unwrap();
+        let key = new_storage_string(&env, key)?;
+
+        let value = env
+            .call_method(
+                self.storage.as_obj(),
+                "get",
+                "(Ljava/lang/String;)Ljava/lang/String;",
+                &[JValue::from(JObject::from(key))],
+            )
+            .and_then(|value| value.l())
+            .map_err(|e| storage_error(&env, "get", e))?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        env.get_string(JString::from(value))
+            .map(|value| Some(value.into()))
+            .map_err(|e| storage_error(&env, "get", e))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), CoreError> {
+        let env = self.java_vm.attach_current_thread().unwrap();
+        let key = new_storage_string(&env, key)?;
+        let value = new_storage_string(&env, value)?;
+
+        let is_set = env
+            .call_method(
+                self.storage.as_obj(),
+                "set",
+                "(Ljava/lang/String;Ljava/lang/String;)Z",
+                &[
+                    JValue::from(JObject::from(key)),
+                    JValue::from(JObject::from(value)),
+                ],
+            )
+            .and_then(|res| res.z())
+            .map_err(|e| storage_error(&env, "set", e))?;
+        check_storage_result("set", is_set)
+    }
+
+    fn remove(&self, key: &str) -> Result<(), CoreError> {
+        let env = self.java_vm.attach_current_thread().unwrap();
+        let key = new_storage_string(&env, key)?;
+
+        let is_removed = env
+            .call_method(
+                self.storage.as_obj(),
+                "remove",
+                "(Ljava/lang/String;)Z",
+                &[JValue::from(JObject::from(key))],
+            )
+            .and_then(|res| res.z())
+            .map_err(|e| storage_error(&env, "remove", e))?;
+        check_storage_result("remove", is_removed)
+    }
+}
+
+fn new_storage_string<'a>(env: &JNIEnv<'a>, str: &str) -> Result<JString<'a>, CoreError> {
+    env.new_string(str)
+        .map_err(|e| storage_error(env, "string", e))
+}
+
+fn storage_error(env: &JNIEnv, operation: &str, e: jni::errors::Error) -> CoreError {
+    // An exception thrown by the storage would otherwise be rethrown when returning to the app
+    let _ = env.exception_clear();
+    CoreError::General(format!("Secure storage {} failed: {:?}", operation, e))
+}
+
+fn check_storage_result(operation: &str, success: bool) -> Result<(), CoreError> {
+    if success {
+        Ok(())
+    } else {
+        Err(CoreError::General(format!(
+            "Secure storage {} failed",
+            operation
+        )))
+    }
+}
+
+fn to_rust_string(env: &JNIEnv, str: JString) -> String {
+    env.get_string(str)
+        .expect("Couldn't create rust string")
+        .into()
+}
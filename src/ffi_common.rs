@@ -0,0 +1,138 @@
+// Platform independent parts of the FFI layers (ffi_ios.rs, ffi_android.rs)
+
+use crate::errors::CoreError;
+use crate::globals::RequestId;
+use crate::networking::Session;
+use crate::session_events::SessionEvent;
+use crate::session_refresh::SessionRefresh;
+use crate::session_state::SessionState;
+use log::*;
+use once_cell::sync::OnceCell;
+use ploc_common::extensions::VecExt;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{mpsc::Sender, Mutex};
+
+static ASYNC_RESULT_SENDER: OnceCell<Mutex<Sender<AsyncResult>>> = OnceCell::new();
+
+// Not directly FFI: serialized to JSON
+#[derive(Debug, Serialize)]
+pub struct FFISession {
+    pub id: String,
+    pub keys: Vec<String>,
+}
+
+impl From<Session> for FFISession {
+    fn from(session: Session) -> Self {
+        FFISession {
+            id: session.id,
+            keys: session.keys.map(|k| k.str),
+        }
+    }
+}
+
+pub fn session_json(session: Session) -> String {
+    serde_json::to_string(&FFISession::from(session)).expect("Couldn't serialize keys")
+}
+
+// e.g. {"status":"peer_key_received","peer_key":"..."}, {"status":"waiting_for_peer"}
+// ready has delete_failed: true if the session couldn't be marked as deleted (retry with delete)
+pub fn refresh_json(refresh: SessionRefresh) -> String {
+    serde_json::to_string(&refresh).expect("Couldn't serialize refresh result")
+}
+
+// e.g. "waiting_for_peer", empty if the session isn't tracked
+pub fn session_state_str(state: Option<SessionState>) -> String {
+    match state.map(serde_json::to_value) {
+        Some(Ok(Value::String(state))) => state,
+        _ => "".to_owned(),
+    }
+}
+
+// Result of an async operation, converted to the platform's representation in the callback thread.
+// status: 1 -> success, otherwise CoreError::status_code()
+#[derive(Debug)]
+pub struct AsyncResult {
+    pub request_id: RequestId,
+    pub status: i32,
+    // Empty if error or if the operation has no result
+    pub result_json: String,
+}
+
+impl AsyncResult {
+    pub fn new<T, F>(request_id: RequestId, res: Result<T, CoreError>, to_json: F) -> AsyncResult
+    where
+        F: FnOnce(T) -> String,
+    {
+        match res {
+            Ok(value) => AsyncResult {
+                request_id,
+                status: 1,
+                result_json: to_json(value),
+            },
+            Err(e) => {
+                error!("Async request {} failed: {:?}", request_id, e);
+                AsyncResult {
+                    request_id,
+                    status: e.status_code(),
+                    result_json: "".to_owned(),
+                }
+            }
+        }
+    }
+
+    pub fn from_session(request_id: RequestId, res: Result<Session, CoreError>) -> AsyncResult {
+        AsyncResult::new(request_id, res, session_json)
+    }
+
+    pub fn from_ack(request_id: RequestId, res: Result<bool, CoreError>) -> AsyncResult {
+        AsyncResult::new(request_id, res, |is_ready| {
+            serde_json::json!({ "is_ready": is_ready }).to_string()
+        })
+    }
+
+    pub fn from_delete(request_id: RequestId, res: Result<(), CoreError>) -> AsyncResult {
+        AsyncResult::new(request_id, res, |_| "".to_owned())
+    }
+
+    pub fn from_refresh(
+        request_id: RequestId,
+        res: Result<SessionRefresh, CoreError>,
+    ) -> AsyncResult {
+        AsyncResult::new(request_id, res, refresh_json)
+    }
+
+    // Session events are delivered with the id of the subscription, e.g. {"type":"peer_acked"}
+    pub fn from_event(request_id: RequestId, event: SessionEvent) -> AsyncResult {
+        AsyncResult::new(request_id, Ok(event), |event| {
+            serde_json::to_string(&event).expect("Couldn't serialize session event")
+        })
+    }
+
+    // End of a subscription: status 1 if the session was deleted, otherwise the error.
+    pub fn from_subscription_end(request_id: RequestId, res: Result<(), CoreError>) -> AsyncResult {
+        AsyncResult::new(request_id, res, |_| "".to_owned())
+    }
+}
+
+// Returns false if a sender was already registered
+pub fn register_async_result_sender(sender: Sender<AsyncResult>) -> bool {
+    ASYNC_RESULT_SENDER.set(Mutex::new(sender)).is_ok()
+}
+
+pub fn send_async_result(result: AsyncResult) {
+    match ASYNC_RESULT_SENDER.get() {
+        Some(sender) => {
+            let sender = sender.lock().expect("Couldn't lock async result sender");
+            if let Err(e) = sender.send(result) {
+                error!("Couldn't send async result to callback: {:?}", e);
+            }
+        }
+        None => {
+            warn!(
+                "No async result callback registered, dropping: {:?}",
+                result
+            );
+        }
+    }
+}
@@ -1,18 +1,42 @@
+use crate::client_session::{ClientSession, Peer};
+use crate::config::CoreConfig;
+use crate::crypto::PrivateKey;
+use crate::errors::CoreError;
+use crate::ffi_common::{
+    refresh_json, register_async_result_sender, send_async_result, session_json, session_state_str,
+    AsyncResult,
+};
 use crate::globals::ack;
+use crate::globals::bootstrap;
+use crate::globals::generate_session_id;
 use crate::globals::join_session_with_id;
-use crate::globals::{create_key_pair, delete, participants, start_session};
+use crate::globals::{
+    ack_async, cancel, delete_async, join_session_with_id_async, participants_async,
+    refresh_session_async, start_session_async, subscribe_session_events, RequestId,
+};
+use crate::globals::{
+    check_short_auth_string, convert_public_key, create_client_session, create_identity_payload,
+    create_key_pair, decrypt_public_key, delete, encrypt_public_key, participants, peer_id,
+    refresh_session, short_auth_string, sign, start_session, validate_identity_payload, verify,
+};
+use crate::globals::{clear_session, load_session, save_session, set_secure_storage};
+use crate::globals::{end_meeting, session_state, start_meeting};
+use crate::key_format::KeyFormat;
 use crate::logger;
 use crate::logger::{CoreLogLevel, CoreLogMessageThreadSafe, SENDER};
+use crate::secure_storage::SecureStorage;
+use crate::session_refresh::RefreshSessionParams;
 use core_foundation::{
     base::TCFType,
-    string::{CFString, CFStringRef, __CFString},
+    string::{__CFString, CFString, CFStringRef},
 };
-use libc::c_char;
+use libc::{c_char, c_void};
 use log::*;
 use mpsc::Receiver;
-use ploc_common::{errors::ServicesError, extensions::VecExt};
-use serde::Serialize;
+use ploc_common::extensions::VecExt;
 use std::{
+    ffi::{CStr, CString},
+    ptr,
     str::FromStr,
     sync::mpsc::{self, Sender},
     thread,
@@ -20,6 +44,10 @@ use std::{
 
 // TODO (post mvp) better error passing to app, ideally success/error should be 2 different structures, with a common root (which has status)
 // depending on status, parse nested structure to expected success type or general error type.
+// Status: 1 -> success, otherwise CoreError::status_code()
+// (0 -> unknown error, 2 -> networking, 3 -> session not found, 4 -> session full, 5 -> already acked, 6 -> cancelled,
+// 7 -> short authentication string mismatch, 8 -> invalid operation in the session's state,
+// 9 -> there's already an active session, 10 -> session id already used, 11 -> malformed session id)
 
 #[repr(C)]
 pub struct FFISessionResult {
@@ -33,13 +61,6 @@ pub struct FFIKeyPairResult {
     private_key: CFStringRef,
     public_key: CFStringRef,
 }
-// Not directly FFI: serialized to JSON
-#[derive(Debug, Serialize)]
-pub struct FFISession {
-    id: String,
-    keys: Vec<String>,
-}
-
 #[repr(C)]
 pub struct FFIAckResult {
     status: i32, // 1 -> success, 0 -> unknown error
@@ -57,12 +78,100 @@ pub struct FFIDeleteResult {
     status: i32, // 1 -> success, 0 -> unknown error
 }
 
+#[repr(C)]
+pub struct FFISignResult {
+    status: i32, // 1 -> success, otherwise error status (see top of file)
+    // Base64 of the raw signature (CryptoKit's rawRepresentation). Empty on error.
+    signature: CFStringRef,
+}
+
+#[repr(C)]
+pub struct FFIIdentityPayloadResult {
+    status: i32, // 1 -> success, otherwise error status (see top of file)
+    // Base64 of the payload bytes. Empty on error.
+    payload: CFStringRef,
+}
+
+#[repr(C)]
+pub struct FFIEncryptedKeyResult {
+    status: i32, // 1 -> success, otherwise error status (see top of file)
+    // Base64 of the encrypted key. Empty on error.
+    encrypted_key: CFStringRef,
+}
+
+#[repr(C)]
+pub struct FFIDecryptedKeyResult {
+    // 1 -> success, otherwise error status (see top of file):
+    // 0 if the password is wrong or the key was modified or is invalid
+    status: i32,
+    // PEM. Empty on error.
+    public_key: CFStringRef,
+}
+
+#[repr(C)]
+pub struct FFIConvertedKeyResult {
+    // 1 -> success, otherwise error status (see top of file): 0 if the key or format is invalid
+    status: i32,
+    // PEM, or base64url for the binary formats. Empty on error.
+    key: CFStringRef,
+}
+
+#[repr(C)]
+pub struct FFIShortAuthStringResult {
+    // 1 -> success, otherwise error status (see top of file): 0 if a key is invalid.
+    // A mismatch with the peer's code is reported by ffi_check_short_auth_string (status 7).
+    status: i32,
+    // 6 digits. Empty on error.
+    digits: CFStringRef,
+    // Emoji separated by spaces. Empty on error.
+    emoji: CFStringRef,
+}
+
+#[repr(C)]
+pub struct FFIVerifyResult {
+    status: i32, // 1 -> success (also if the signature is invalid), otherwise error status
+    is_valid: bool,
+}
+
+#[repr(C)]
+pub struct FFIClientSessionResult {
+    status: i32, // 1 -> success, otherwise error status (see top of file)
+    // JSON of the client session, including the private key. Empty on error.
+    session_json: CFStringRef,
+}
+
+#[repr(C)]
+pub struct FFIRefreshSessionResult {
+    status: i32, // 1 -> success, otherwise error status (see top of file)
+    // e.g. {"status":"peer_key_received","peer_key":"..."} (see refresh_json). Empty on error.
+    result_json: CFStringRef,
+}
+
 #[no_mangle]
-pub unsafe extern "C" fn ffi_bootstrap(level: CoreLogLevel, app_only: bool) -> i32 {
+pub unsafe extern "C" fn ffi_bootstrap(
+    level: CoreLogLevel,
+    app_only: bool,
+    config_json: *const c_char,
+) -> i32 {
     let level_string = level.to_string();
     let filter_level = LevelFilter::from_str(&level_string).expect("Incorrect log level selected");
     logger::setup_logger(filter_level, app_only);
-    1
+
+    let config = match CoreConfig::from_json(cstring_to_str(&config_json)) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Invalid core config: {}", e);
+            return 0;
+        }
+    };
+
+    match bootstrap(config) {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error bootstrapping: {:?}", e);
+            0
+        }
+    }
 }
 
 #[no_mangle]
@@ -70,25 +179,23 @@ pub unsafe extern "C" fn ffi_create_key_pair() -> FFIKeyPairResult {
     let res = create_key_pair();
 
     match res {
-        Ok(key_pair) => {
-            let private_str = base64::encode(key_pair.private);
-            let public_str = base64::encode(key_pair.public);
-            FFIKeyPairResult {
-                status: 1,
-                private_key: private_str.to_CFStringRef_and_forget(),
-                public_key: public_str.to_CFStringRef_and_forget(),
-            }
-        }
+        // PEM, same format as CryptoKit's pemRepresentation
+        Ok(key_pair) => FFIKeyPairResult {
+            status: 1,
+            private_key: key_pair
+                .private
+                .expose()
+                .to_owned()
+                .to_CFStringRef_and_forget(),
+            public_key: key_pair.public.to_CFStringRef_and_forget(),
+        },
         Err(e) => {
-            error!("Error creating session: {:?}", e);
+            error!("Error creating key pair: {:?}", e);
             // TODO proper error result, not "error with empty success fields"
             let private_str = "".to_owned();
             let public_str = "".to_owned();
             FFIKeyPairResult {
-                status: match e {
-                    ServicesError::Networking(_) => 2,
-                    _ => 0
-                },
+                status: e.status_code(),
                 private_key: private_str.to_CFStringRef_and_forget(),
                 public_key: public_str.to_CFStringRef_and_forget(),
             }
@@ -96,23 +203,261 @@ pub unsafe extern "C" fn ffi_create_key_pair() -> FFIKeyPairResult {
     }
 }
 
+// Id for a new session, 32 hex characters (see session_id.rs)
+#[no_mangle]
+pub unsafe extern "C" fn ffi_generate_session_id() -> CFStringRef {
+    generate_session_id().to_CFStringRef_and_forget()
+}
+
+// Hex SHA-256 of the PEM public key
+#[no_mangle]
+pub unsafe extern "C" fn ffi_peer_id(public_key: *const c_char) -> CFStringRef {
+    let public_key_str: String = cstring_to_str(&public_key).into();
+    peer_id(public_key_str).to_CFStringRef_and_forget()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_sign(
+    private_key: *const c_char,
+    payload: *const u8,
+    payload_len: usize,
+) -> FFISignResult {
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    let payload = bytes(payload, payload_len);
+
+    match sign(&private_key, payload) {
+        Ok(signature) => FFISignResult {
+            status: 1,
+            signature: base64::encode(signature).to_CFStringRef_and_forget(),
+        },
+        Err(e) => {
+            error!("Error signing: {:?}", e);
+            FFISignResult {
+                status: e.status_code(),
+                signature: "".to_owned().to_CFStringRef_and_forget(),
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_verify(
+    public_key: *const c_char,
+    payload: *const u8,
+    payload_len: usize,
+    signature: *const u8,
+    signature_len: usize,
+) -> FFIVerifyResult {
+    let public_key_str = cstring_to_str(&public_key);
+    let payload = bytes(payload, payload_len);
+    let signature = bytes(signature, signature_len);
+
+    match verify(public_key_str, payload, signature) {
+        Ok(is_valid) => FFIVerifyResult {
+            status: 1,
+            is_valid,
+        },
+        Err(e) => {
+            error!("Error verifying signature: {:?}", e);
+            FFIVerifyResult {
+                status: e.status_code(),
+                is_valid: false,
+            }
+        }
+    }
+}
+
+// Payload to send to the peer over BLE. Only the peer can read / validate it.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_create_identity_payload(
+    private_key: *const c_char,
+    peer_public_key: *const c_char,
+) -> FFIIdentityPayloadResult {
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    let peer_public_key_str = cstring_to_str(&peer_public_key);
+
+    match create_identity_payload(&private_key, peer_public_key_str) {
+        Ok(payload) => FFIIdentityPayloadResult {
+            status: 1,
+            payload: base64::encode(payload).to_CFStringRef_and_forget(),
+        },
+        Err(e) => {
+            error!("Error creating identity payload: {:?}", e);
+            FFIIdentityPayloadResult {
+                status: e.status_code(),
+                payload: "".to_owned().to_CFStringRef_and_forget(),
+            }
+        }
+    }
+}
+
+// is_valid: whether the payload was created by the peer (with peer_public_key) for us, recently
+// and wasn't received before (replay)
+#[no_mangle]
+pub unsafe extern "C" fn ffi_validate_identity_payload(
+    private_key: *const c_char,
+    peer_public_key: *const c_char,
+    payload: *const u8,
+    payload_len: usize,
+) -> FFIVerifyResult {
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    let peer_public_key_str = cstring_to_str(&peer_public_key);
+    let payload = bytes(payload, payload_len);
+
+    match validate_identity_payload(&private_key, peer_public_key_str, payload) {
+        Ok(is_valid) => FFIVerifyResult {
+            status: 1,
+            is_valid,
+        },
+        Err(e) => {
+            error!("Error validating identity payload: {:?}", e);
+            FFIVerifyResult {
+                status: e.status_code(),
+                is_valid: false,
+            }
+        }
+    }
+}
+
+// Colocated pairing: our public key, encrypted with the pairing password
+#[no_mangle]
+pub unsafe extern "C" fn ffi_encrypt_public_key(
+    public_key: *const c_char,
+    password: *const c_char,
+) -> FFIEncryptedKeyResult {
+    let public_key_str = cstring_to_str(&public_key);
+    let password_str = cstring_to_str(&password);
+
+    match encrypt_public_key(public_key_str, password_str) {
+        Ok(encrypted) => FFIEncryptedKeyResult {
+            status: 1,
+            encrypted_key: base64::encode(encrypted).to_CFStringRef_and_forget(),
+        },
+        Err(e) => {
+            error!("Error encrypting public key: {:?}", e);
+            FFIEncryptedKeyResult {
+                status: e.status_code(),
+                encrypted_key: "".to_owned().to_CFStringRef_and_forget(),
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_decrypt_public_key(
+    encrypted_key: *const u8,
+    encrypted_key_len: usize,
+    password: *const c_char,
+) -> FFIDecryptedKeyResult {
+    let encrypted_key = bytes(encrypted_key, encrypted_key_len);
+    let password_str = cstring_to_str(&password);
+
+    match decrypt_public_key(encrypted_key, password_str) {
+        Ok(public_key) => FFIDecryptedKeyResult {
+            status: 1,
+            public_key: public_key.to_CFStringRef_and_forget(),
+        },
+        Err(e) => {
+            error!("Error decrypting public key: {:?}", e);
+            FFIDecryptedKeyResult {
+                status: e.status_code(),
+                public_key: "".to_owned().to_CFStringRef_and_forget(),
+            }
+        }
+    }
+}
+
+// key: PEM, or base64url for the binary formats (see key_format.rs)
+#[no_mangle]
+pub unsafe extern "C" fn ffi_convert_public_key(
+    key: *const c_char,
+    from: KeyFormat,
+    to: KeyFormat,
+) -> FFIConvertedKeyResult {
+    let key_str = cstring_to_str(&key);
+
+    match convert_public_key(key_str, from, to) {
+        Ok(key) => FFIConvertedKeyResult {
+            status: 1,
+            key: key.to_CFStringRef_and_forget(),
+        },
+        Err(e) => {
+            error!("Error converting public key: {:?}", e);
+            FFIConvertedKeyResult {
+                status: e.status_code(),
+                key: "".to_owned().to_CFStringRef_and_forget(),
+            }
+        }
+    }
+}
+
+// Code to compare with the peer, same for both peers
 #[no_mangle]
+pub unsafe extern "C" fn ffi_short_auth_string(
+    own_key: *const c_char,
+    peer_key: *const c_char,
+) -> FFIShortAuthStringResult {
+    let own_key_str = cstring_to_str(&own_key);
+    let peer_key_str = cstring_to_str(&peer_key);
+
+    match short_auth_string(own_key_str, peer_key_str) {
+        Ok(sas) => FFIShortAuthStringResult {
+            status: 1,
+            digits: sas.digits.to_CFStringRef_and_forget(),
+            emoji: sas.emoji.join(" ").to_CFStringRef_and_forget(),
+        },
+        Err(e) => {
+            error!("Error creating short authentication string: {:?}", e);
+            FFIShortAuthStringResult {
+                status: e.status_code(),
+                digits: "".to_owned().to_CFStringRef_and_forget(),
+                emoji: "".to_owned().to_CFStringRef_and_forget(),
+            }
+        }
+    }
+}
+
+// peer_code: digits or emoji shown by the peer.
+// Returns 1 if it matches, 7 if not (the session shouldn't be used), 0 if a key is invalid,
+// otherwise error status.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_check_short_auth_string(
+    own_key: *const c_char,
+    peer_key: *const c_char,
+    peer_code: *const c_char,
+) -> i32 {
+    let own_key_str = cstring_to_str(&own_key);
+    let peer_key_str = cstring_to_str(&peer_key);
+    let peer_code_str = cstring_to_str(&peer_code);
+
+    match check_short_auth_string(own_key_str, peer_key_str, peer_code_str) {
+        Ok(()) => 1,
+        Err(e) => {
+            error!("Short authentication string check failed: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+#[no_mangle]
+// private_key: of the session key pair, signs the request
+// If there's already an active session: status 9 or, if reuse_active, the active session.
+// Status 10 if the session id is used by another session.
+#[deprecated(note = "Blocking and not cancellable, use ffi_create_session_async")]
 pub unsafe extern "C" fn ffi_create_session(
     session_id: *const c_char,
     key: *const c_char,
+    private_key: *const c_char,
+    reuse_active: bool,
 ) -> FFISessionResult {
     let session_id_str: String = cstring_to_str(&session_id).into();
     let key_str: String = cstring_to_str(&key).into();
-    let res = start_session(session_id_str, key_str);
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    let res = start_session(session_id_str, key_str, private_key, reuse_active);
 
     match res {
         Ok(session) => {
-            let ffi_session = FFISession {
-                id: session.id,
-                keys: session.keys.map(|k| k.str),
-            };
-            let session_str = serde_json::to_string(&ffi_session).expect("Couldn't serialize keys");
-            let cf_string_ref = session_str.to_CFStringRef_and_forget();
+            let cf_string_ref = session_json(session).to_CFStringRef_and_forget();
 
             FFISessionResult {
                 status: 1,
@@ -124,10 +469,7 @@ pub unsafe extern "C" fn ffi_create_session(
             let session_str = "";
             let cf_string_ref = session_str.to_owned().to_CFStringRef_and_forget();
             FFISessionResult {
-                status: match e {
-                    ServicesError::Networking(_) => 2,
-                    _ => 0
-                },
+                status: e.status_code(),
                 session_json: cf_string_ref,
             }
         }
@@ -135,22 +477,20 @@ pub unsafe extern "C" fn ffi_create_session(
 }
 
 #[no_mangle]
+#[deprecated(note = "Blocking and not cancellable, use ffi_join_session_async")]
 pub unsafe extern "C" fn ffi_join_session(
     session_id: *const c_char,
     key: *const c_char,
+    private_key: *const c_char,
 ) -> FFISessionResult {
     let session_id_str: String = cstring_to_str(&session_id).into();
     let key_str: String = cstring_to_str(&key).into();
-    let res = join_session_with_id(session_id_str, key_str);
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    let res = join_session_with_id(session_id_str, key_str, private_key);
 
     match res {
         Ok(session) => {
-            let ffi_session = FFISession {
-                id: session.id,
-                keys: session.keys.map(|k| k.str),
-            };
-            let session_str = serde_json::to_string(&ffi_session).expect("Couldn't serialize keys");
-            let cf_string_ref = session_str.to_CFStringRef_and_forget();
+            let cf_string_ref = session_json(session).to_CFStringRef_and_forget();
 
             FFISessionResult {
                 status: 1,
@@ -163,10 +503,7 @@ pub unsafe extern "C" fn ffi_join_session(
             let cf_string_ref = session_str.to_owned().to_CFStringRef_and_forget();
 
             FFISessionResult {
-                status: match e {
-                    ServicesError::Networking(_) => 2,
-                    _ => 0
-                },
+                status: e.status_code(),
                 session_json: cf_string_ref,
             }
         }
@@ -174,9 +511,16 @@ pub unsafe extern "C" fn ffi_join_session(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ffi_ack(uuid: *const c_char, stored_participants: i32) -> FFIAckResult {
-    let uuid_str: String = cstring_to_str(&uuid).into();
-    let res = ack(uuid_str, stored_participants);
+// public_key: own public key, the peer id is derived from it. private_key signs the request.
+#[deprecated(note = "Blocking and not cancellable, use ffi_ack_async")]
+pub unsafe extern "C" fn ffi_ack(
+    public_key: *const c_char,
+    stored_participants: i32,
+    private_key: *const c_char,
+) -> FFIAckResult {
+    let public_key_str: String = cstring_to_str(&public_key).into();
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    let res = ack(public_key_str, stored_participants, private_key);
 
     match res {
         Ok(is_ready) => FFIAckResult {
@@ -186,10 +530,7 @@ pub unsafe extern "C" fn ffi_ack(uuid: *const c_char, stored_participants: i32)
         Err(e) => {
             error!("Error acking: {:?}", e);
             FFIAckResult {
-                status: match e {
-                    ServicesError::Networking(_) => 2,
-                    _ => 0
-                },
+                status: e.status_code(),
                 is_ready: false,
             }
         }
@@ -197,18 +538,14 @@ pub unsafe extern "C" fn ffi_ack(uuid: *const c_char, stored_participants: i32)
 }
 
 #[no_mangle]
+#[deprecated(note = "Blocking and not cancellable, use ffi_participants_async")]
 pub unsafe extern "C" fn ffi_participants(session_id: *const c_char) -> FFIParticipantsResult {
     let session_id_str: String = cstring_to_str(&session_id).into();
     let res = participants(session_id_str);
 
     match res {
         Ok(session) => {
-            let ffi_session = FFISession {
-                id: session.id,
-                keys: session.keys.map(|k| k.str),
-            };
-            let session_str = serde_json::to_string(&ffi_session).expect("Couldn't serialize keys");
-            let cf_string_ref = session_str.to_CFStringRef_and_forget();
+            let cf_string_ref = session_json(session).to_CFStringRef_and_forget();
 
             FFIParticipantsResult {
                 status: 1,
@@ -221,10 +558,7 @@ pub unsafe extern "C" fn ffi_participants(session_id: *const c_char) -> FFIParti
             let cf_string_ref = session_str.to_owned().to_CFStringRef_and_forget();
 
             FFIParticipantsResult {
-                status: match e {
-                    ServicesError::Networking(_) => 2,
-                    _ => 0
-                },
+                status: e.status_code(),
                 session_json: cf_string_ref,
             }
         }
@@ -232,22 +566,435 @@ pub unsafe extern "C" fn ffi_participants(session_id: *const c_char) -> FFIParti
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ffi_delete(peer_id: *const c_char) -> FFIDeleteResult {
-    let session_id_str: String = cstring_to_str(&peer_id).into();
-    let res = delete(session_id_str);
+// public_key: own public key, the peer id is derived from it. private_key signs the request.
+#[deprecated(note = "Blocking and not cancellable, use ffi_delete_async")]
+pub unsafe extern "C" fn ffi_delete(
+    public_key: *const c_char,
+    private_key: *const c_char,
+) -> FFIDeleteResult {
+    let public_key_str: String = cstring_to_str(&public_key).into();
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    let res = delete(public_key_str, private_key);
 
     match res {
         Ok(_) => FFIDeleteResult { status: 1 },
         Err(e) => {
             error!("Error marking as deleted: {:?}", e);
-            FFIDeleteResult { status: match e {
-                ServicesError::Networking(_) => 2,
-                _ => 0
-            } }
+            FFIDeleteResult {
+                status: e.status_code(),
+            }
         }
     }
 }
 
+// Fetches participants, acks and marks the session as deleted when ready (see session_refresh.rs).
+// peer_key: the stored peer key, null if there's none yet.
+#[no_mangle]
+#[deprecated(note = "Blocking and not cancellable, use ffi_refresh_session_async")]
+pub unsafe extern "C" fn ffi_refresh_session(
+    session_id: *const c_char,
+    own_key: *const c_char,
+    own_private_key: *const c_char,
+    peer_key: *const c_char,
+) -> FFIRefreshSessionResult {
+    let params = to_refresh_params(session_id, own_key, own_private_key, peer_key);
+
+    match refresh_session(params) {
+        Ok(refresh) => FFIRefreshSessionResult {
+            status: 1,
+            result_json: refresh_json(refresh).to_CFStringRef_and_forget(),
+        },
+        Err(e) => {
+            error!("Error refreshing session: {:?}", e);
+            FFIRefreshSessionResult {
+                status: e.status_code(),
+                result_json: "".to_owned().to_CFStringRef_and_forget(),
+            }
+        }
+    }
+}
+
+unsafe fn to_refresh_params(
+    session_id: *const c_char,
+    own_key: *const c_char,
+    own_private_key: *const c_char,
+    peer_key: *const c_char,
+) -> RefreshSessionParams {
+    RefreshSessionParams {
+        session_id: cstring_to_str(&session_id).into(),
+        own_key: cstring_to_str(&own_key).into(),
+        own_private_key: PrivateKey::from(cstring_to_str(&own_private_key)),
+        peer_key: if peer_key.is_null() {
+            None
+        } else {
+            Some(cstring_to_str(&peer_key).into())
+        },
+    }
+}
+
+// Local session with a new key pair, for the app to store (see client_session.rs)
+#[no_mangle]
+pub unsafe extern "C" fn ffi_create_client_session(
+    session_id: *const c_char,
+    created_by_me: bool,
+) -> FFIClientSessionResult {
+    let session_id_str: String = cstring_to_str(&session_id).into();
+    to_client_session_result(create_client_session(session_id_str, created_by_me))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_client_session_with_peer(
+    session_json: *const c_char,
+    peer_public_key: *const c_char,
+) -> FFIClientSessionResult {
+    let peer = Peer {
+        public_key: cstring_to_str(&peer_public_key).into(),
+    };
+    let res = ClientSession::from_json(cstring_to_str(&session_json))
+        .map(|session| session.with_peer(peer));
+    to_client_session_result(res)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_client_session_with_is_ready(
+    session_json: *const c_char,
+    is_ready: bool,
+) -> FFIClientSessionResult {
+    let res = ClientSession::from_json(cstring_to_str(&session_json))
+        .map(|session| session.with_is_ready(is_ready));
+    to_client_session_result(res)
+}
+
+fn to_client_session_result(res: Result<ClientSession, CoreError>) -> FFIClientSessionResult {
+    match res {
+        Ok(session) => FFIClientSessionResult {
+            status: 1,
+            session_json: session.to_json().to_CFStringRef_and_forget(),
+        },
+        Err(e) => {
+            error!("Client session error: {:?}", e);
+            FFIClientSessionResult {
+                status: e.status_code(),
+                session_json: "".to_owned().to_CFStringRef_and_forget(),
+            }
+        }
+    }
+}
+
+#[repr(C)]
+pub struct FFIAsyncResult {
+    request_id: RequestId,
+    status: i32, // 1 -> success, otherwise error status (see top of file)
+    // Same JSON as the result of the blocking counterpart, e.g. FFISession. Empty on error.
+    result_json: CFStringRef,
+}
+
+// Async operations return a request id immediately, the result is delivered later
+// to the callback registered with register_async_result_callback, with the same request id.
+// The blocking counterparts are deprecated: they can't be cancelled (see ffi_cancel).
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_create_session_async(
+    session_id: *const c_char,
+    key: *const c_char,
+    private_key: *const c_char,
+    reuse_active: bool,
+) -> RequestId {
+    let session_id_str: String = cstring_to_str(&session_id).into();
+    let key_str: String = cstring_to_str(&key).into();
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    start_session_async(
+        session_id_str,
+        key_str,
+        private_key,
+        reuse_active,
+        |request_id, res| send_async_result(AsyncResult::from_session(request_id, res)),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_join_session_async(
+    session_id: *const c_char,
+    key: *const c_char,
+    private_key: *const c_char,
+) -> RequestId {
+    let session_id_str: String = cstring_to_str(&session_id).into();
+    let key_str: String = cstring_to_str(&key).into();
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    join_session_with_id_async(session_id_str, key_str, private_key, |request_id, res| {
+        send_async_result(AsyncResult::from_session(request_id, res))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_ack_async(
+    public_key: *const c_char,
+    stored_participants: i32,
+    private_key: *const c_char,
+) -> RequestId {
+    let public_key_str: String = cstring_to_str(&public_key).into();
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    ack_async(
+        public_key_str,
+        stored_participants,
+        private_key,
+        |request_id, res| send_async_result(AsyncResult::from_ack(request_id, res)),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_participants_async(session_id: *const c_char) -> RequestId {
+    let session_id_str: String = cstring_to_str(&session_id).into();
+    participants_async(session_id_str, |request_id, res| {
+        send_async_result(AsyncResult::from_session(request_id, res))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_delete_async(
+    public_key: *const c_char,
+    private_key: *const c_char,
+) -> RequestId {
+    let public_key_str: String = cstring_to_str(&public_key).into();
+    let private_key = PrivateKey::from(cstring_to_str(&private_key));
+    delete_async(public_key_str, private_key, |request_id, res| {
+        send_async_result(AsyncResult::from_delete(request_id, res))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_refresh_session_async(
+    session_id: *const c_char,
+    own_key: *const c_char,
+    own_private_key: *const c_char,
+    peer_key: *const c_char,
+) -> RequestId {
+    let params = to_refresh_params(session_id, own_key, own_private_key, peer_key);
+    refresh_session_async(params, |request_id, res| {
+        send_async_result(AsyncResult::from_refresh(request_id, res))
+    })
+}
+
+// Session events are delivered to the async result callback with the returned request id
+// and the event as JSON, e.g. {"type":"peer_joined","key":"..."}, {"type":"peer_acked"},
+// {"type":"session_deleted"}. When the subscription ends, a last result with empty JSON is sent.
+// Unsubscribe with ffi_cancel.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_subscribe_session_events(
+    session_id: *const c_char,
+    own_key: *const c_char,
+) -> RequestId {
+    let session_id_str: String = cstring_to_str(&session_id).into();
+    let own_key_str: String = cstring_to_str(&own_key).into();
+    subscribe_session_events(
+        session_id_str,
+        own_key_str,
+        |request_id, event| send_async_result(AsyncResult::from_event(request_id, event)),
+        |request_id, res| send_async_result(AsyncResult::from_subscription_end(request_id, res)),
+    )
+}
+
+// Cancels an async operation. Its result is delivered with status 6 (cancelled).
+// Returns 1 if cancelled, 0 if there's no operation in progress with this id.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_cancel(request_id: RequestId) -> i32 {
+    if cancel(request_id) {
+        1
+    } else {
+        0
+    }
+}
+
+// e.g. "waiting_for_peer" (see session_state.rs), empty if the session isn't tracked
+#[no_mangle]
+pub unsafe extern "C" fn ffi_session_state(session_id: *const c_char) -> CFStringRef {
+    let session_id_str = cstring_to_str(&session_id);
+    session_state_str(session_state(session_id_str)).to_CFStringRef_and_forget()
+}
+
+// The meeting follows the pairing (state ready).
+// Returns 1 on success, 8 if not valid in the session's state, otherwise error status.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_start_meeting(session_id: *const c_char) -> i32 {
+    let session_id_str = cstring_to_str(&session_id);
+    match start_meeting(session_id_str) {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error starting meeting: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_end_meeting(session_id: *const c_char) -> i32 {
+    let session_id_str = cstring_to_str(&session_id);
+    match end_meeting(session_id_str) {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error ending meeting: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+// The app's secure storage (e.g. the Keychain), see secure_storage.rs. Called on any thread.
+// The callbacks return 1 on success, 0 on error.
+#[repr(C)]
+pub struct FFISecureStorage {
+    // Sets *value to null if there's no value, otherwise to a string allocated with malloc
+    // (e.g. strdup), which the core frees.
+    get: unsafe extern "C" fn(key: *const c_char, value: *mut *mut c_char) -> i32,
+    set: unsafe extern "C" fn(key: *const c_char, value: *const c_char) -> i32,
+    remove: unsafe extern "C" fn(key: *const c_char) -> i32,
+}
+
+impl SecureStorage for FFISecureStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, CoreError> {
+        let key = to_cstring(key)?;
+        let mut value: *mut c_char = ptr::null_mut();
+        check_storage_status("get", unsafe { (self.get)(key.as_ptr(), &mut value) })?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        unsafe {
+            let value_cstr = CStr::from_ptr(value);
+            let res = value_cstr.to_str().map(|value| value.to_owned());
+            // Contains the private key
+            ptr::write_bytes(value, 0, value_cstr.to_bytes().len());
+            libc::free(value as *mut c_void);
+            res.map(Some)
+                .map_err(|_| CoreError::General("Stored value isn't utf8".to_owned()))
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), CoreError> {
+        let (key, value) = (to_cstring(key)?, to_cstring(value)?);
+        check_storage_status("set", unsafe { (self.set)(key.as_ptr(), value.as_ptr()) })
+    }
+
+    fn remove(&self, key: &str) -> Result<(), CoreError> {
+        let key = to_cstring(key)?;
+        check_storage_status("remove", unsafe { (self.remove)(key.as_ptr()) })
+    }
+}
+
+fn to_cstring(str: &str) -> Result<CString, CoreError> {
+    CString::new(str).map_err(|_| CoreError::General("String contains nul byte".to_owned()))
+}
+
+fn check_storage_status(operation: &str, status: i32) -> Result<(), CoreError> {
+    if status == 1 {
+        Ok(())
+    } else {
+        Err(CoreError::General(format!(
+            "Secure storage {} failed, status: {}",
+            operation, status
+        )))
+    }
+}
+
+// Returns 1 if registered, 0 if there was already a storage registered
+#[no_mangle]
+pub unsafe extern "C" fn register_secure_storage(storage: FFISecureStorage) -> i32 {
+    if set_secure_storage(Box::new(storage)) {
+        1
+    } else {
+        0
+    }
+}
+
+// Stores the client session (JSON, see ffi_create_client_session) in the registered secure storage,
+// replacing the stored one. Returns 1 on success, otherwise error status.
+#[no_mangle]
+pub unsafe extern "C" fn ffi_save_session(session_json: *const c_char) -> i32 {
+    let res = ClientSession::from_json(cstring_to_str(&session_json))
+        .and_then(|session| save_session(&session));
+    match res {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error saving session: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+// session_json is empty if there's no stored session
+#[no_mangle]
+pub unsafe extern "C" fn ffi_load_session() -> FFIClientSessionResult {
+    match load_session() {
+        Ok(None) => FFIClientSessionResult {
+            status: 1,
+            session_json: "".to_owned().to_CFStringRef_and_forget(),
+        },
+        Ok(Some(session)) => to_client_session_result(Ok(session)),
+        Err(e) => to_client_session_result(Err(e)),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ffi_clear_session() -> i32 {
+    match clear_session() {
+        Ok(_) => 1,
+        Err(e) => {
+            error!("Error clearing session: {:?}", e);
+            e.status_code()
+        }
+    }
+}
+
+pub trait AsyncResultCallback {
+    fn call(&self, result: FFIAsyncResult);
+}
+
+impl AsyncResultCallback for unsafe extern "C" fn(FFIAsyncResult) {
+    fn call(&self, result: FFIAsyncResult) {
+        unsafe {
+            self(result);
+        }
+    }
+}
+
+// Returns 1 if registered, 0 if there was already a callback registered
+#[no_mangle]
+pub unsafe extern "C" fn register_async_result_callback(
+    callback: unsafe extern "C" fn(FFIAsyncResult),
+) -> i32 {
+    if register_async_result_callback_internal(Box::new(callback)) {
+        1
+    } else {
+        0
+    }
+}
+
+fn register_async_result_callback_internal(callback: Box<dyn AsyncResultCallback>) -> bool {
+    // Make callback implement Send (marker for thread safe, basically) https://doc.rust-lang.org/std/marker/trait.Send.html
+    let callback = unsafe {
+        std::mem::transmute::<Box<dyn AsyncResultCallback>, Box<dyn AsyncResultCallback + Send>>(
+            callback,
+        )
+    };
+
+    let (tx, rx): (Sender<AsyncResult>, Receiver<AsyncResult>) = mpsc::channel();
+
+    if !register_async_result_sender(tx) {
+        error!("Async result callback already registered");
+        return false;
+    }
+
+    // Thread waits for results and calls the callback
+    thread::spawn(move || {
+        for result in rx.iter() {
+            callback.call(FFIAsyncResult {
+                request_id: result.request_id,
+                status: result.status,
+                result_json: result.result_json.to_CFStringRef_and_forget(),
+            });
+        }
+    });
+    true
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn greet(who: *const c_char) -> CFStringRef {
     let str: String = cstring_to_str(&who).into();
@@ -294,6 +1041,17 @@ unsafe fn cstring_to_str<'a>(cstring: &'a *const c_char) -> &str {
     raw.to_str().expect("Couldn't convert c string to slice")
 }
 
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        // Swift may pass null for empty Data
+        return &[];
+    }
+    if ptr.is_null() {
+        panic!("bytes pointer is null")
+    }
+    std::slice::from_raw_parts(ptr, len)
+}
+
 fn to_cf_str(str: String) -> CFStringRef {
     let cf_string = CFString::new(&str);
     let cf_string_ref = cf_string.as_concrete_TypeRef();
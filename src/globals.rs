@@ -1,14 +1,68 @@
+use crate::ble_payload::{self, IdentityValidator};
+use crate::client_session::ClientSession;
+use crate::colocated_pairing;
+use crate::config::CoreConfig;
+use crate::crypto::{self, PrivateKey};
+use crate::errors::CoreError;
+use crate::key_format::{self, KeyFormat};
 use crate::networking::{RemoteSessionApi, RemoteSessionApiImpl, Session};
-// use openssl::rsa::Rsa;
+use crate::networking_async::{AsyncRemoteSessionApi, AsyncRemoteSessionApiImpl};
+use crate::secure_storage::SecureStorage;
+use crate::session_events::{SessionEvent, SessionEventsApi};
+use crate::session_id;
+use crate::session_refresh::{self, RefreshSessionParams, SessionRefresh};
+use crate::session_state::{SessionLifecycleEvent, SessionOperation, SessionState, SessionStates};
+use crate::short_auth_string::{self, ShortAuthString};
 
+use chrono::Utc;
+use futures::future::{AbortHandle, Abortable};
 use log::*;
-use ploc_common::errors::ServicesError;
+use once_cell::sync::OnceCell;
 use ploc_common::model_types::PublicKey;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::{Mutex, MutexGuard},
+};
+use tokio::runtime::Runtime;
+use zeroize::Zeroizing;
 
-#[derive(Debug, Clone)]
+static CONTEXT: OnceCell<CoreContext> = OnceCell::new();
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+// Set by the apps, independently of bootstrapping (see secure_storage.rs)
+static SECURE_STORAGE: OnceCell<Box<dyn SecureStorage>> = OnceCell::new();
+
+// Key of the client session in the secure storage. The apps have one session at a time.
+const CLIENT_SESSION_STORAGE_KEY: &str = "client_session";
+
+// Identifies an async operation. Passed to its completion and used to cancel it.
+pub type RequestId = u64;
+
+// Long-lived state owned by the core, created when bootstrapping.
+struct CoreContext {
+    // To detect bootstrapping again with a different config
+    config: CoreConfig,
+    api: RemoteSessionApiImpl,
+    async_api: AsyncRemoteSessionApiImpl,
+    events_api: SessionEventsApi,
+    // Runs the async operations
+    runtime: Runtime,
+    // Async operations that haven't completed yet
+    in_flight: Mutex<HashMap<RequestId, AbortHandle>>,
+    // Remembers the received identity payloads, to reject replays
+    identity_validator: Mutex<IdentityValidator>,
+    // Lifecycle of the sessions, to reject operations that aren't valid in their state
+    session_states: Mutex<SessionStates>,
+}
+
+// PEM encoded (see crypto.rs). The private key is redacted in Debug.
+#[derive(Debug)]
 pub struct KeyPair {
-    pub private: Vec<u8>,
-    pub public: Vec<u8>,
+    pub private: PrivateKey,
+    pub public: String,
 }
 
 // TODO rename this in ClientParticipant
@@ -18,69 +72,775 @@ pub struct ClientSessionKey {
     pub key: PublicKey,
 }
 
-pub fn start_session(session_id: String, key: String) -> Result<Session, ServicesError> {
-    // TODO check if id already exists in db?
+// Can be called only once. Subsequent calls with the same config are ignored, with a different
+// config they fail (the first context is kept).
+pub fn bootstrap(config: CoreConfig) -> Result<(), CoreError> {
+    info!("Bootstrapping core with config: {:?}", config);
+    if let Some(context) = CONTEXT.get() {
+        return check_bootstrapped_config(context, &config);
+    }
+    let runtime = tokio::runtime::Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .thread_name("core-runtime")
+        .build()
+        .map_err(|e| CoreError::General(format!("Couldn't create runtime: {:?}", e)))?;
+    // The async clients have to be created in the runtime's context
+    let (async_api, events_api) = runtime.enter(|| {
+        Ok::<_, CoreError>((
+            AsyncRemoteSessionApiImpl::new(config.clone())?,
+            SessionEventsApi::new(config.clone())?,
+        ))
+    })?;
+    let identity_validator = IdentityValidator::new(&config.ble);
+    let api = RemoteSessionApiImpl::new(config.clone())?;
+
+    let new_context = CoreContext {
+        config: config.clone(),
+        api,
+        async_api,
+        events_api,
+        runtime,
+        in_flight: Mutex::new(HashMap::new()),
+        identity_validator: Mutex::new(identity_validator),
+        session_states: Mutex::new(SessionStates::default()),
+    };
+    match CONTEXT.set(new_context) {
+        Ok(()) => Ok(()),
+        // Bootstrapped concurrently
+        Err(_) => check_bootstrapped_config(context()?, &config),
+    }
+}
+
+fn check_bootstrapped_config(context: &CoreContext, config: &CoreConfig) -> Result<(), CoreError> {
+    if &context.config == config {
+        warn!("Core already bootstrapped, ignoring");
+        Ok(())
+    } else {
+        Err(CoreError::General(
+            "Core already bootstrapped with a different config".to_owned(),
+        ))
+    }
+}
+
+// Err if not bootstrapped: the apps may call the core before (a panic can't unwind across FFI)
+fn context() -> Result<&'static CoreContext, CoreError> {
+    CONTEXT
+        .get()
+        .ok_or_else(|| CoreError::General("Core not bootstrapped".to_owned()))
+}
+
+// A poisoned lock (a thread panicked while holding it) is reported as an error instead of panicking
+fn lock<'a, T>(mutex: &'a Mutex<T>, name: &str) -> Result<MutexGuard<'a, T>, CoreError> {
+    mutex
+        .lock()
+        .map_err(|e| CoreError::General(format!("{} lock poisoned: {}", name, e)))
+}
+
+impl CoreContext {
+    fn in_flight(&self) -> Result<MutexGuard<'_, HashMap<RequestId, AbortHandle>>, CoreError> {
+        lock(&self.in_flight, "In flight requests")
+    }
+}
+
+fn api() -> Result<&'static RemoteSessionApiImpl, CoreError> {
+    Ok(&context()?.api)
+}
+
+fn async_api() -> Result<&'static AsyncRemoteSessionApiImpl, CoreError> {
+    Ok(&context()?.async_api)
+}
+
+// private_key: of the session key pair, signs the requests (see endpoints::prepare)
+// The apps have one session at a time: if there's already an active one (see session_state.rs),
+// returns SessionAlreadyActive or, if reuse_active, the active session (from the backend).
+pub fn start_session(
+    session_id: String,
+    key: String,
+    private_key: PrivateKey,
+    reuse_active: bool,
+) -> Result<Session, CoreError> {
+    session_id::validate_session_id(&session_id)?;
+    if let Some(active_id) = reserve_session_to_start(&session_id, reuse_active)? {
+        info!("Reusing active session: {}", active_id);
+        return participants(active_id);
+    }
+    let _reservation = StartReservation(session_id.clone());
+    check_session_id_available(api()?.participants(session_id.clone()), &key)?;
 
-    let res = join_session_with_id(session_id, key);
+    let res = join_session_with_id(session_id, key, private_key);
     debug!("Start session res: {:?}", res);
     res
 }
 
-pub fn join_session_with_id(id: String, key: String) -> Result<Session, ServicesError> {
+pub fn join_session_with_id(
+    id: String,
+    key: String,
+    private_key: PrivateKey,
+) -> Result<Session, CoreError> {
     debug!("Joining session with id: {}, key: {}", id, key);
-    let api = RemoteSessionApiImpl {};
-    let res = api
-        .join_session(ClientSessionKey {
-            session_id: id,
-            key: PublicKey { str: key },
-        })
-        .map_err(ServicesError::from);
+    session_id::validate_session_id(&id)?;
+    let session_key = ClientSessionKey {
+        session_id: id.clone(),
+        key: PublicKey { str: key },
+    };
+    session_states()?.check_join(&id, &crypto::peer_id(&session_key.key))?;
+    let res = api()?.join_session(session_key, &private_key);
+    let res = apply_result(Some(&id), res, joined);
     debug!("Join session res: {:?}", res);
     res
 }
 
-pub fn create_key_pair() -> Result<KeyPair, ServicesError> {
-    // it was not possible to get rust-openssl working:
-    // https://stackoverflow.com/questions/63513401/how-to-use-rust-openssl-on-ios
-    // https://github.com/sfackler/rust-openssl/issues/1331
-    // for now generating keys in the apps
-    // note also that we should use EC instead of RSA (doing this now on iOS)
+pub fn create_key_pair() -> Result<KeyPair, CoreError> {
+    let res = crypto::create_key_pair();
+    debug!("Created key pair, success: {}", res.is_ok());
+    res
+}
 
-    // let rsa = Rsa::generate(4096)?;
+// For new sessions: ids from elsewhere (e.g. deep links) are validated when joining
+pub fn generate_session_id() -> String {
+    session_id::generate_session_id()
+}
 
-    // let private_key = rsa.private_key_to_pem()?;
-    // let public_key = rsa.public_key_to_pem()?;
+// New local session with a new key pair, to store in the apps (see client_session.rs)
+pub fn create_client_session(
+    session_id: String,
+    created_by_me: bool,
+) -> Result<ClientSession, CoreError> {
+    let key_pair = crypto::create_key_pair()?;
+    let session = ClientSession::new(session_id, key_pair, created_by_me);
+    debug!("Created client session: {:?}", session);
+    Ok(session)
+}
 
-    // Ok(KeyPair {
-    //     private: private_key,
-    //     public: public_key,
-    // })
-    Ok(KeyPair {
-        private: vec![],
-        public: vec![],
-    })
+// Can be set only once. Returns false if a storage was already set.
+pub fn set_secure_storage(storage: Box<dyn SecureStorage>) -> bool {
+    SECURE_STORAGE.set(storage).is_ok()
 }
 
-pub fn ack(uuid: String, stored_participants: i32) -> Result<bool, ServicesError> {
-    let api = RemoteSessionApiImpl {};
-    let res = api
-        .ack(uuid, stored_participants)
-        .map_err(ServicesError::from);
+fn secure_storage() -> Result<&'static dyn SecureStorage, CoreError> {
+    SECURE_STORAGE
+        .get()
+        .map(|storage| storage.as_ref())
+        .ok_or_else(|| CoreError::General("No secure storage set".to_owned()))
+}
+
+// Replaces the stored session
+pub fn save_session(session: &ClientSession) -> Result<(), CoreError> {
+    let json = Zeroizing::new(session.to_json());
+    let res = secure_storage()?.set(CLIENT_SESSION_STORAGE_KEY, &json);
+    debug!("Save session: {} res: {:?}", session.id, res);
+    res
+}
+
+// None if there's no stored session. Resumes tracking the session's state (see session_state.rs).
+pub fn load_session() -> Result<Option<ClientSession>, CoreError> {
+    let json = match secure_storage()?.get(CLIENT_SESSION_STORAGE_KEY)? {
+        Some(json) => Zeroizing::new(json),
+        None => return Ok(None),
+    };
+    let session = ClientSession::from_json(&json)?;
+    session_states()?.resume(&session);
+    debug!("Loaded session: {:?}", session);
+    Ok(Some(session))
+}
+
+// Abandons the stored session: it isn't active anymore (see start_session)
+pub fn clear_session() -> Result<(), CoreError> {
+    let storage = secure_storage()?;
+    if let Some(json) = storage.get(CLIENT_SESSION_STORAGE_KEY)? {
+        let session = ClientSession::from_json(&Zeroizing::new(json))?;
+        session_states()?.forget(&session.id);
+    }
+    let res = storage.remove(CLIENT_SESSION_STORAGE_KEY);
+    debug!("Clear session res: {:?}", res);
+    res
+}
+
+pub fn peer_id(key: String) -> String {
+    crypto::peer_id(&PublicKey { str: key })
+}
+
+// Raw (r || s) P-521 signature, compatible with CryptoKit's rawRepresentation
+pub fn sign(private_key: &PrivateKey, payload: &[u8]) -> Result<Vec<u8>, CoreError> {
+    crypto::sign(private_key, payload)
+}
+
+pub fn verify(public_key: &str, payload: &[u8], signature: &[u8]) -> Result<bool, CoreError> {
+    let res = crypto::verify(public_key, payload, signature);
+    debug!("Verify signature res: {:?}", res);
+    res
+}
+
+// Encrypted for the peer: only they can validate it (see ble_payload.rs).
+// Valid only for a short time and once, so the apps have to create new ones regularly.
+pub fn create_identity_payload(
+    private_key: &PrivateKey,
+    peer_public_key: &str,
+) -> Result<Vec<u8>, CoreError> {
+    ble_payload::create_identity_payload(private_key, peer_public_key, Utc::now())
+}
+
+pub fn validate_identity_payload(
+    private_key: &PrivateKey,
+    peer_public_key: &str,
+    payload: &[u8],
+) -> Result<bool, CoreError> {
+    let mut validator = lock(&context()?.identity_validator, "Identity validator")?;
+    let res = validator.validate(private_key, peer_public_key, payload, Utc::now());
+    debug!("Validate identity payload res: {:?}", res);
+    res.map(|validation| validation.is_valid())
+}
+
+// Colocated pairing: the public key, encrypted with the pairing password (see colocated_pairing.rs)
+pub fn encrypt_public_key(public_key: &str, password: &str) -> Result<Vec<u8>, CoreError> {
+    colocated_pairing::encrypt_public_key(public_key, password)
+}
+
+pub fn decrypt_public_key(encrypted: &[u8], password: &str) -> Result<String, CoreError> {
+    colocated_pairing::decrypt_public_key(encrypted, password)
+}
+
+// E.g. to advertise the key compactly over BLE (see key_format.rs). Binary formats are base64url.
+pub fn convert_public_key(key: &str, from: KeyFormat, to: KeyFormat) -> Result<String, CoreError> {
+    key_format::convert_public_key(key, from, to)
+}
+
+// To compare with the peer out of band, once we have its key (see short_auth_string.rs)
+pub fn short_auth_string(own_key: &str, peer_key: &str) -> Result<ShortAuthString, CoreError> {
+    short_auth_string::short_auth_string(own_key, peer_key)
+}
+
+pub fn check_short_auth_string(
+    own_key: &str,
+    peer_key: &str,
+    peer_code: &str,
+) -> Result<(), CoreError> {
+    let res = short_auth_string::check_short_auth_string(own_key, peer_key, peer_code);
+    if let Err(CoreError::AuthStringMismatch) = res {
+        warn!("Short authentication string mismatch");
+    }
+    res
+}
+
+// Acks for the peer with this (own) public key
+pub fn ack(
+    key: String,
+    stored_participants: i32,
+    private_key: PrivateKey,
+) -> Result<bool, CoreError> {
+    let peer_id = crypto::peer_id(&PublicKey { str: key });
+    let session_id = check_peer_operation(&peer_id, SessionOperation::Ack)?;
+    let res = api()?.ack(peer_id, stored_participants, &private_key);
+    let res = apply_result(session_id.as_deref(), res, acked);
 
     debug!("Ack res: {:?}", res);
     res
 }
 
-pub fn participants(session_id: String) -> Result<Session, ServicesError> {
-    let api = RemoteSessionApiImpl {};
-    let res = api.participants(session_id).map_err(ServicesError::from);
+pub fn participants(session_id: String) -> Result<Session, CoreError> {
+    session_states()?.check(&session_id, SessionOperation::Participants)?;
+    let res = api()?.participants(session_id.clone());
+    let res = apply_result(Some(&session_id), res, participants_fetched);
     debug!("Participants res: {:?}", res);
     res
 }
 
-pub fn delete(peer_id: String) -> Result<(), ServicesError> {
-    let api = RemoteSessionApiImpl {};
-    let res = api.delete(peer_id).map_err(ServicesError::from);
+// Marks the backend session as deleted for the peer with this (own) public key
+pub fn delete(key: String, private_key: PrivateKey) -> Result<(), CoreError> {
+    let peer_id = crypto::peer_id(&PublicKey { str: key });
+    let session_id = check_peer_operation(&peer_id, SessionOperation::Delete)?;
+    let res = api()?.delete(peer_id, &private_key);
+    let res = apply_result(session_id.as_deref(), res, |_| {
+        SessionLifecycleEvent::Deleted
+    });
     debug!("Mark as deleted res: {:?}", res);
     res
 }
+
+pub fn refresh_session(params: RefreshSessionParams) -> Result<SessionRefresh, CoreError> {
+    debug!("Refreshing session: {}", params.session_id);
+    let session_id = params.session_id.clone();
+    session_states()?.check(&session_id, SessionOperation::Participants)?;
+    let res = session_refresh::refresh_session(api()?, params);
+    let res = apply_refresh(&session_id, res);
+    debug!("Refresh session res: {:?}", res);
+    res
+}
+
+// Async variants: return immediately, completion is called on a runtime thread.
+
+pub fn start_session_async<F>(
+    session_id: String,
+    key: String,
+    private_key: PrivateKey,
+    reuse_active: bool,
+    completion: F,
+) -> RequestId
+where
+    F: FnOnce(RequestId, Result<Session, CoreError>) + Send + 'static,
+{
+    spawn(
+        async move {
+            session_id::validate_session_id(&session_id)?;
+            if let Some(active_id) = reserve_session_to_start(&session_id, reuse_active)? {
+                info!("Reusing active session (async): {}", active_id);
+                return fetch_participants_async(active_id).await;
+            }
+            let _reservation = StartReservation(session_id.clone());
+            let res = async_api()?.participants(session_id.clone()).await;
+            check_session_id_available(res, &key)?;
+            join_async(session_id, key, private_key).await
+        },
+        completion,
+    )
+}
+
+pub fn join_session_with_id_async<F>(
+    id: String,
+    key: String,
+    private_key: PrivateKey,
+    completion: F,
+) -> RequestId
+where
+    F: FnOnce(RequestId, Result<Session, CoreError>) + Send + 'static,
+{
+    debug!("Joining session (async) with id: {}, key: {}", id, key);
+    spawn(join_async(id, key, private_key), completion)
+}
+
+async fn join_async(
+    id: String,
+    key: String,
+    private_key: PrivateKey,
+) -> Result<Session, CoreError> {
+    session_id::validate_session_id(&id)?;
+    let session_key = ClientSessionKey {
+        session_id: id.clone(),
+        key: PublicKey { str: key },
+    };
+    session_states()?.check_join(&id, &crypto::peer_id(&session_key.key))?;
+    let res = async_api()?.join_session(session_key, &private_key).await;
+    apply_result(Some(&id), res, joined)
+}
+
+pub fn ack_async<F>(
+    key: String,
+    stored_participants: i32,
+    private_key: PrivateKey,
+    completion: F,
+) -> RequestId
+where
+    F: FnOnce(RequestId, Result<bool, CoreError>) + Send + 'static,
+{
+    let peer_id = crypto::peer_id(&PublicKey { str: key });
+    spawn(
+        async move {
+            let session_id = check_peer_operation(&peer_id, SessionOperation::Ack)?;
+            let res = async_api()?
+                .ack(peer_id, stored_participants, &private_key)
+                .await;
+            apply_result(session_id.as_deref(), res, acked)
+        },
+        completion,
+    )
+}
+
+pub fn participants_async<F>(session_id: String, completion: F) -> RequestId
+where
+    F: FnOnce(RequestId, Result<Session, CoreError>) + Send + 'static,
+{
+    spawn(fetch_participants_async(session_id), completion)
+}
+
+async fn fetch_participants_async(session_id: String) -> Result<Session, CoreError> {
+    session_states()?.check(&session_id, SessionOperation::Participants)?;
+    let res = async_api()?.participants(session_id.clone()).await;
+    apply_result(Some(&session_id), res, participants_fetched)
+}
+
+pub fn delete_async<F>(key: String, private_key: PrivateKey, completion: F) -> RequestId
+where
+    F: FnOnce(RequestId, Result<(), CoreError>) + Send + 'static,
+{
+    let peer_id = crypto::peer_id(&PublicKey { str: key });
+    spawn(
+        async move {
+            let session_id = check_peer_operation(&peer_id, SessionOperation::Delete)?;
+            let res = async_api()?.delete(peer_id, &private_key).await;
+            apply_result(session_id.as_deref(), res, |_| {
+                SessionLifecycleEvent::Deleted
+            })
+        },
+        completion,
+    )
+}
+
+pub fn refresh_session_async<F>(params: RefreshSessionParams, completion: F) -> RequestId
+where
+    F: FnOnce(RequestId, Result<SessionRefresh, CoreError>) + Send + 'static,
+{
+    debug!("Refreshing session (async): {}", params.session_id);
+    spawn(
+        async move {
+            let session_id = params.session_id.clone();
+            session_states()?.check(&session_id, SessionOperation::Participants)?;
+            let res = session_refresh::refresh_session_async(async_api()?, params).await;
+            apply_refresh(&session_id, res)
+        },
+        completion,
+    )
+}
+
+// None if the session wasn't joined since bootstrapping (see session_state.rs)
+pub fn session_state(session_id: &str) -> Option<SessionState> {
+    session_states().ok()?.state(session_id)
+}
+
+// The apps report the meeting, which follows the pairing. Err if the session isn't tracked.
+pub fn start_meeting(session_id: &str) -> Result<SessionState, CoreError> {
+    apply_meeting_event(session_id, SessionLifecycleEvent::MeetingStarted)
+}
+
+pub fn end_meeting(session_id: &str) -> Result<SessionState, CoreError> {
+    apply_meeting_event(session_id, SessionLifecycleEvent::MeetingEnded)
+}
+
+fn apply_meeting_event(
+    session_id: &str,
+    event: SessionLifecycleEvent,
+) -> Result<SessionState, CoreError> {
+    let res = session_states()?.apply(session_id, event);
+    debug!("Meeting event: {:?} res: {:?}", event, res);
+    res?.ok_or_else(|| CoreError::General(format!("Session isn't tracked: {}", session_id)))
+}
+
+// The id of the active session (other than session_id) if it should be reused. Otherwise reserves
+// session_id, in the same lock: a concurrent start can't pass the check before we joined.
+// The reservation has to be released with StartReservation.
+fn reserve_session_to_start(
+    session_id: &str,
+    reuse_active: bool,
+) -> Result<Option<String>, CoreError> {
+    let mut states = session_states()?;
+    match states.active_session() {
+        // Starting the same session again, e.g. to retry
+        Some(active_id) if active_id == session_id => {}
+        Some(active_id) if reuse_active => return Ok(Some(active_id)),
+        Some(active_id) => {
+            return Err(CoreError::SessionAlreadyActive {
+                session_id: active_id,
+            })
+        }
+        None => {}
+    }
+    states.reserve(session_id)?;
+    Ok(None)
+}
+
+// Releases the reserved session id when dropped: once joined (it's active then) or if starting
+// failed or was cancelled
+struct StartReservation(String);
+
+impl Drop for StartReservation {
+    fn drop(&mut self) {
+        if let Ok(mut states) = session_states() {
+            states.release(&self.0);
+        }
+    }
+}
+
+// Result of fetching the participants of the session to start. The session id has to be new (the
+// backend returns no keys for unknown ids), unless the backend session has only our key (a retry).
+// The backend has the last word when joining: the session can still be created in between.
+fn check_session_id_available(
+    res: Result<Session, CoreError>,
+    own_key: &str,
+) -> Result<(), CoreError> {
+    match res {
+        Err(CoreError::SessionNotFound) => Ok(()),
+        Ok(session) if session.keys.iter().all(|key| key.str == own_key) => Ok(()),
+        Ok(session) => {
+            warn!("Session id already exists in the backend: {}", session.id);
+            Err(CoreError::SessionIdTaken)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn session_states() -> Result<MutexGuard<'static, SessionStates>, CoreError> {
+    lock(&context()?.session_states, "Session states")
+}
+
+// ack and delete identify the session with the (own) peer id.
+// Returns the session id, None if the session isn't tracked.
+fn check_peer_operation(
+    peer_id: &str,
+    operation: SessionOperation,
+) -> Result<Option<String>, CoreError> {
+    let states = session_states()?;
+    let session_id = states.session_id(peer_id);
+    if let Some(session_id) = &session_id {
+        states.check(session_id, operation)?;
+    }
+    Ok(session_id)
+}
+
+// Updates the state of the session with the result of the operation. If the result isn't valid in
+// the state (e.g. the peer's key disappeared), it's replaced with the error.
+fn apply_result<T, F>(
+    session_id: Option<&str>,
+    res: Result<T, CoreError>,
+    to_event: F,
+) -> Result<T, CoreError>
+where
+    F: FnOnce(&T) -> SessionLifecycleEvent,
+{
+    let value = res?;
+    if let Some(session_id) = session_id {
+        session_states()?.apply(session_id, to_event(&value))?;
+    }
+    Ok(value)
+}
+
+// The backend session has our key and, once joined, the peer's
+fn joined(session: &Session) -> SessionLifecycleEvent {
+    SessionLifecycleEvent::Joined {
+        has_peer: session.keys.len() > 1,
+    }
+}
+
+fn participants_fetched(session: &Session) -> SessionLifecycleEvent {
+    SessionLifecycleEvent::ParticipantsFetched {
+        has_peer: session.keys.len() > 1,
+    }
+}
+
+fn acked(is_ready: &bool) -> SessionLifecycleEvent {
+    SessionLifecycleEvent::Acked {
+        is_ready: *is_ready,
+    }
+}
+
+// A refresh fetches the participants and, if there's a peer, acks. Marking as deleted once ready
+// doesn't change the state.
+fn apply_refresh(
+    session_id: &str,
+    res: Result<SessionRefresh, CoreError>,
+) -> Result<SessionRefresh, CoreError> {
+    let refresh = res?;
+    let mut states = session_states()?;
+    let has_peer = refresh != SessionRefresh::WaitingForPeer;
+    states.apply(
+        session_id,
+        SessionLifecycleEvent::ParticipantsFetched { has_peer },
+    )?;
+    if has_peer {
+        let is_ready = matches!(refresh, SessionRefresh::Ready { .. });
+        states.apply(session_id, SessionLifecycleEvent::Acked { is_ready })?;
+    }
+    Ok(refresh)
+}
+
+// Delivers session events (peer joined, acked, session deleted) to on_event, until the session
+// is deleted or the subscription is cancelled (with cancel(request_id)).
+// completion is called when the subscription ends.
+pub fn subscribe_session_events<E, F>(
+    session_id: String,
+    own_key: String,
+    on_event: E,
+    completion: F,
+) -> RequestId
+where
+    E: Fn(RequestId, SessionEvent) + Send + Sync + 'static,
+    F: FnOnce(RequestId, Result<(), CoreError>) + Send + 'static,
+{
+    debug!("Subscribing to events of session: {}", session_id);
+    let request_id = next_request_id();
+    let operation = async move {
+        let context = context()?;
+        context
+            .events_api
+            .watch(&context.async_api, session_id, own_key, move |event| {
+                on_event(request_id, event)
+            })
+            .await
+    };
+    spawn_with_id(request_id, operation, completion);
+    request_id
+}
+
+// Aborts the operation (retries and the http request in progress).
+// Its completion is called with CoreError::Cancelled.
+// Returns false if there's no operation in progress with this id (e.g. it already completed).
+pub fn cancel(request_id: RequestId) -> bool {
+    let handle = match context().and_then(|context| context.in_flight()) {
+        Ok(mut in_flight) => in_flight.remove(&request_id),
+        Err(e) => {
+            error!("Couldn't cancel request: {}, error: {:?}", request_id, e);
+            None
+        }
+    };
+    debug!(
+        "Cancelling request: {}, in flight: {}",
+        request_id,
+        handle.is_some()
+    );
+    match handle {
+        Some(handle) => {
+            handle.abort();
+            true
+        }
+        None => false,
+    }
+}
+
+fn next_request_id() -> RequestId {
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn spawn<T, Op, F>(operation: Op, completion: F) -> RequestId
+where
+    T: std::fmt::Debug + Send + 'static,
+    Op: Future<Output = Result<T, CoreError>> + Send + 'static,
+    F: FnOnce(RequestId, Result<T, CoreError>) + Send + 'static,
+{
+    let request_id = next_request_id();
+    spawn_with_id(request_id, operation, completion);
+    request_id
+}
+
+// For operations that need to know their request id (e.g. to pass it to other callbacks).
+// If the core isn't bootstrapped, completion is called right away, on the calling thread.
+fn spawn_with_id<T, Op, F>(request_id: RequestId, operation: Op, completion: F)
+where
+    T: std::fmt::Debug + Send + 'static,
+    Op: Future<Output = Result<T, CoreError>> + Send + 'static,
+    F: FnOnce(RequestId, Result<T, CoreError>) + Send + 'static,
+{
+    let context = match context() {
+        Ok(context) => context,
+        Err(e) => return completion(request_id, Err(e)),
+    };
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    // Registered before spawning, so it's there when the operation completes
+    match context.in_flight() {
+        Ok(mut in_flight) => in_flight.insert(request_id, abort_handle),
+        Err(e) => return completion(request_id, Err(e)),
+    };
+    let operation = Abortable::new(operation, abort_registration);
+
+    context.runtime.spawn(async move {
+        let res = operation.await;
+        // If it's not in flight anymore, it was cancelled (possibly right after finishing)
+        let cancelled = context
+            .in_flight()
+            .map(|mut in_flight| in_flight.remove(&request_id).is_none());
+        let res = match (res, cancelled) {
+            (_, Err(e)) => Err(e),
+            (Ok(res), Ok(false)) => res,
+            _ => Err(CoreError::Cancelled),
+        };
+        debug!("Async request {} res: {:?}", request_id, res);
+        completion(request_id, res);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secure_storage::EncryptedFileStorage;
+    use std::{sync::mpsc, time::Duration};
+
+    #[test]
+    fn bootstrapping_again_with_other_config_fails() {
+        bootstrap(CoreConfig::default()).unwrap();
+        let other = CoreConfig {
+            base_url: "http://127.0.0.1:9000/".to_owned(),
+            ..CoreConfig::default()
+        };
+
+        assert!(matches!(bootstrap(other), Err(CoreError::General(_))));
+        assert!(bootstrap(CoreConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn cancel_completes_with_cancelled_error() {
+        bootstrap(CoreConfig::default()).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        let request_id = spawn(
+            futures::future::pending::<Result<(), CoreError>>(),
+            move |id, res| {
+                tx.send((id, res)).unwrap();
+            },
+        );
+
+        assert!(cancel(request_id));
+
+        let (id, res) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(id, request_id);
+        assert!(matches!(res, Err(CoreError::Cancelled)));
+    }
+
+    #[test]
+    fn cancel_after_completion_is_noop() {
+        bootstrap(CoreConfig::default()).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        let request_id = spawn(async { Ok(true) }, move |_, res| {
+            tx.send(res).unwrap();
+        });
+
+        let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(res, Ok(true)));
+        assert!(!cancel(request_id));
+    }
+
+    #[test]
+    fn loads_saved_session_and_resumes_its_state() {
+        bootstrap(CoreConfig::default()).unwrap();
+        let dir = std::env::temp_dir().join(format!("core-globals-{}", uuid::Uuid::new_v4()));
+        let storage = EncryptedFileStorage::open(&dir, "password").unwrap();
+        assert!(set_secure_storage(Box::new(storage)));
+        let session = create_client_session("stored".to_owned(), true)
+            .unwrap()
+            .with_is_ready(true);
+
+        save_session(&session).unwrap();
+        let loaded = load_session().unwrap();
+
+        assert_eq!(loaded, Some(session));
+        assert_eq!(session_state("stored"), Some(SessionState::Ready));
+        clear_session().unwrap();
+        assert_eq!(load_session().unwrap(), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn session_id_is_available_without_keys_or_only_with_own_key() {
+        let session = |keys: Vec<&str>| Session {
+            id: "1".to_owned(),
+            keys: keys
+                .into_iter()
+                .map(|key| PublicKey {
+                    str: key.to_owned(),
+                })
+                .collect(),
+        };
+
+        assert!(check_session_id_available(Ok(session(vec![])), "own").is_ok());
+        assert!(check_session_id_available(Err(CoreError::SessionNotFound), "own").is_ok());
+        assert!(check_session_id_available(Ok(session(vec!["own"])), "own").is_ok());
+        for taken in [session(vec!["other"]), session(vec!["own", "other"])] {
+            assert!(matches!(
+                check_session_id_available(Ok(taken), "own"),
+                Err(CoreError::SessionIdTaken)
+            ));
+        }
+        assert!(matches!(
+            check_session_id_available(Err(CoreError::SessionFull), "own"),
+            Err(CoreError::SessionFull)
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+// Conversions of public keys between PEM, the format the backend expects (see crypto.rs), and more
+// compact encodings, e.g. for BLE advertisements or QR codes.
+// Binary encodings are passed as text with base64url (no padding): safe in URLs and QR codes.
+// Private keys never leave the device, so they stay PEM.
+//
+// Decoding always returns the PEM in the format of crypto::create_key_pair: the peer id is the hash
+// of the PEM string (see crypto::peer_id), so a round trip has to give exactly the same string.
+
+use crate::{crypto, errors::CoreError};
+use p521::{
+    elliptic_curve::sec1::ToEncodedPoint,
+    pkcs8::{DecodePublicKey, EncodePublicKey},
+    PublicKey,
+};
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyFormat {
+    // CryptoKit's pemRepresentation
+    Pem = 0,
+    // SPKI, i.e. the content of the PEM (CryptoKit's derRepresentation), 158 bytes
+    Der = 1,
+    // SEC1 compressed point (CryptoKit's compressedRepresentation), 67 bytes
+    Compressed = 2,
+}
+
+impl KeyFormat {
+    // For the FFI: None if the code doesn't correspond to a format
+    pub fn from_code(code: i32) -> Option<KeyFormat> {
+        match code {
+            0 => Some(KeyFormat::Pem),
+            1 => Some(KeyFormat::Der),
+            2 => Some(KeyFormat::Compressed),
+            _ => None,
+        }
+    }
+}
+
+// Pem returns the utf8 bytes of the PEM
+pub fn encode_public_key(public_key_pem: &str, format: KeyFormat) -> Result<Vec<u8>, CoreError> {
+    let public_key = crypto::public_key_from_pem(public_key_pem)?;
+    match format {
+        KeyFormat::Pem => Ok(crypto::public_key_to_pem(&public_key)?.into_bytes()),
+        KeyFormat::Der => public_key
+            .to_public_key_der()
+            .map(|der| der.as_bytes().to_vec())
+            .map_err(|e| CoreError::General(format!("Couldn't encode public key: {:?}", e))),
+        KeyFormat::Compressed => Ok(public_key.to_encoded_point(true).as_bytes().to_vec()),
+    }
+}
+
+pub fn decode_public_key(encoded: &[u8], format: KeyFormat) -> Result<String, CoreError> {
+    let public_key = match format {
+        KeyFormat::Pem => {
+            let pem = std::str::from_utf8(encoded)
+                .map_err(|e| CoreError::General(format!("PEM isn't utf8: {:?}", e)))?;
+            crypto::public_key_from_pem(pem)?
+        }
+        KeyFormat::Der => PublicKey::from_public_key_der(encoded)
+            .map_err(|e| CoreError::General(format!("Invalid public key DER: {:?}", e)))?,
+        KeyFormat::Compressed => PublicKey::from_sec1_bytes(encoded)
+            .map_err(|e| CoreError::General(format!("Invalid compressed public key: {:?}", e)))?,
+    };
+    crypto::public_key_to_pem(&public_key)
+}
+
+// Text to text, as passed over the FFI: PEM as is, the binary formats as base64url.
+pub fn convert_public_key(key: &str, from: KeyFormat, to: KeyFormat) -> Result<String, CoreError> {
+    let pem = match from {
+        KeyFormat::Pem => decode_public_key(key.as_bytes(), from)?,
+        _ => decode_public_key(&from_base64url(key)?, from)?,
+    };
+    match to {
+        KeyFormat::Pem => Ok(pem),
+        _ => Ok(to_base64url(&encode_public_key(&pem, to)?)),
+    }
+}
+
+pub fn to_base64url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+pub fn from_base64url(str: &str) -> Result<Vec<u8>, CoreError> {
+    base64::decode_config(str.trim(), base64::URL_SAFE_NO_PAD)
+        .map_err(|e| CoreError::General(format!("Invalid base64url: {:?}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [KeyFormat; 3] = [KeyFormat::Pem, KeyFormat::Der, KeyFormat::Compressed];
+
+    fn public_key() -> String {
+        crypto::create_key_pair().unwrap().public
+    }
+
+    #[test]
+    fn round_trips_all_formats() {
+        let pem = public_key();
+
+        for from in &FORMATS {
+            let key = convert_public_key(&pem, KeyFormat::Pem, *from).unwrap();
+            for to in &FORMATS {
+                let converted = convert_public_key(&key, *from, *to).unwrap();
+                let back = convert_public_key(&converted, *to, KeyFormat::Pem).unwrap();
+                assert_eq!(back, pem);
+            }
+        }
+    }
+
+    #[test]
+    fn der_is_content_of_pem() {
+        let pem = public_key();
+        let body: String = pem.lines().filter(|l| !l.starts_with("-----")).collect();
+
+        let der = encode_public_key(&pem, KeyFormat::Der).unwrap();
+
+        assert_eq!(der, base64::decode(body).unwrap());
+        assert_eq!(der.len(), 158);
+    }
+
+    #[test]
+    fn compressed_is_smallest() {
+        let pem = public_key();
+
+        let compressed = encode_public_key(&pem, KeyFormat::Compressed).unwrap();
+
+        assert_eq!(compressed.len(), 67);
+        assert!(compressed[0] == 2 || compressed[0] == 3);
+        let text = convert_public_key(&pem, KeyFormat::Pem, KeyFormat::Compressed).unwrap();
+        assert_eq!(text.len(), 90);
+        assert!(!text.contains(&['+', '/', '='][..]));
+    }
+
+    #[test]
+    fn decodes_to_canonical_pem() {
+        let pem = public_key();
+
+        // E.g. with the line break other PEM encoders add
+        let res = decode_public_key(format!("{}\n", pem).as_bytes(), KeyFormat::Pem);
+
+        assert_eq!(res.unwrap(), pem);
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let compressed = encode_public_key(&public_key(), KeyFormat::Compressed).unwrap();
+
+        assert!(decode_public_key(&compressed, KeyFormat::Der).is_err());
+        assert!(decode_public_key(&compressed[1..], KeyFormat::Compressed).is_err());
+        assert!(encode_public_key("not a key", KeyFormat::Der).is_err());
+        assert!(convert_public_key("not base64url!", KeyFormat::Der, KeyFormat::Pem).is_err());
+        assert_eq!(KeyFormat::from_code(3), None);
+    }
+}
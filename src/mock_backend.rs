@@ -0,0 +1,405 @@
+// In-process stand-in for the backend, for hermetic tests of the networking layer.
+// Implements /key, /ready, /part and /del with the same request / response types and envelope.
+// /events streams the events pushed with push_events, one batch per connection, and is
+// unsupported (404) if there are none.
+// Signed requests are verified like the backend does: /key against the posted key, /ready and
+// /del against the key of the peer.
+// Acking again with the same count returns the current readiness, with another count already_acked.
+// Each instance listens on its own port and has its own (empty) state.
+
+use crate::{
+    config::{CoreConfig, EventsConfig, RetryConfig},
+    crypto,
+    endpoints::{self, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    session_events::SessionEvent,
+};
+use ploc_common::{
+    model_types::PublicKey,
+    networking_types::{
+        AckRequestParams, AckSessionResult, JoinSessionResult, ParticipantsRequestParams,
+        ParticipantsResult, PeerDeleteSesionParams, SessionKeyRequestParams,
+    },
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub struct MockBackend {
+    server: Arc<Server>,
+    state: Arc<Mutex<State>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct State {
+    sessions: HashMap<String, MockSession>,
+    // Returned (in order) instead of handling the request, by path
+    injected: HashMap<String, VecDeque<Injected>>,
+    // Number of requests received, by path
+    requests: HashMap<String, usize>,
+    // Sent to the next connections to /events, a batch per connection
+    events: VecDeque<Vec<SessionEvent>>,
+}
+
+#[derive(Default)]
+struct MockSession {
+    keys: Vec<String>,
+    // Peer id -> participants the peer acked having stored
+    acks: HashMap<String, i32>,
+    deleted: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Injected {
+    status: u16,
+    code: Option<String>,
+    retry_after: Option<String>,
+}
+
+type Reply = (u16, Value);
+
+impl MockBackend {
+    pub fn start() -> MockBackend {
+        let server = Arc::new(Server::http("127.0.0.1:0").expect("Couldn't start mock backend"));
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let thread = {
+            let server = server.clone();
+            let state = state.clone();
+            thread::spawn(move || {
+                // Ends when the server is unblocked (on drop)
+                for request in server.incoming_requests() {
+                    handle(&state, request);
+                }
+            })
+        };
+
+        MockBackend {
+            server,
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    // Config pointing to this backend, with short retry intervals to keep the tests fast.
+    pub fn config(&self) -> CoreConfig {
+        CoreConfig {
+            base_url: format!("http://{}/", self.server.server_addr()),
+            connect_timeout_ms: 1_000,
+            request_timeout_ms: 2_000,
+            retry: RetryConfig {
+                initial_interval_ms: 10,
+                max_interval_ms: 50,
+                multiplier: 1.5,
+                max_elapsed_time_ms: 1_000,
+            },
+            events: EventsConfig {
+                reconnect_interval_ms: 10,
+                max_reconnect_attempts: 3,
+                max_reconnect_interval_ms: 50,
+                idle_timeout_ms: 1_000,
+                polling_interval_ms: 10,
+            },
+            ..CoreConfig::default()
+        }
+    }
+
+    // The next request to path is answered with this status (and no payload) instead of being handled.
+    pub fn fail_next(&self, path: &str, status: u16) {
+        self.inject(path, status, None, None);
+    }
+
+    pub fn fail_next_with_retry_after(&self, path: &str, status: u16, retry_after: Duration) {
+        self.inject(path, status, None, Some(retry_after.as_secs().to_string()));
+    }
+
+    // Error envelope with a backend error code, e.g. "session_full"
+    pub fn fail_next_with_code(&self, path: &str, status: u16, code: &str) {
+        self.inject(path, status, Some(code.to_owned()), None);
+    }
+
+    // The next connection to /events receives these events, then is closed (as if disconnected)
+    pub fn push_events(&self, events: Vec<SessionEvent>) {
+        self.state.lock().unwrap().events.push_back(events);
+    }
+
+    pub fn requests(&self, path: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.requests.get(path).cloned().unwrap_or(0)
+    }
+
+    pub fn peer_id(key: &str) -> String {
+        crypto::peer_id(&PublicKey {
+            str: key.to_owned(),
+        })
+    }
+
+    fn inject(&self, path: &str, status: u16, code: Option<String>, retry_after: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .injected
+            .entry(path.to_owned())
+            .or_default()
+            .push_back(Injected {
+                status,
+                code,
+                retry_after,
+            });
+    }
+}
+
+impl Drop for MockBackend {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn handle(state: &Mutex<State>, mut request: Request) {
+    let path = request
+        .url()
+        .trim_start_matches('/')
+        .split('?')
+        .next()
+        .unwrap_or("")
+        .to_owned();
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let signature = signature(&request, &path, &body);
+
+    let mut state = state.lock().unwrap();
+    *state.requests.entry(path.clone()).or_default() += 1;
+
+    let injected = state
+        .injected
+        .get_mut(&path)
+        .and_then(|injected| injected.pop_front());
+
+    let response = match injected {
+        Some(injected) => {
+            let response = to_response(error(injected.status, injected.code.as_deref()));
+            match injected.retry_after {
+                Some(retry_after) => response.with_header(header("Retry-After", &retry_after)),
+                None => response,
+            }
+        }
+        None if path == "events" => events(&mut state),
+        None => to_response(route(&mut state, &path, &body, signature.as_ref())),
+    };
+
+    let _ = request.respond(response);
+}
+
+// Signed message and signature, if the request has the signature headers
+struct Signature {
+    message: String,
+    signature: Vec<u8>,
+}
+
+impl Signature {
+    fn is_valid(&self, public_key: &str) -> bool {
+        crypto::verify(public_key, self.message.as_bytes(), &self.signature).unwrap_or(false)
+    }
+}
+
+fn signature(request: &Request, path: &str, body: &str) -> Option<Signature> {
+    let header = |name: &'static str| {
+        request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str().to_owned())
+    };
+    let timestamp = header(TIMESTAMP_HEADER)?;
+    let signature = base64::decode(header(SIGNATURE_HEADER)?).ok()?;
+    let method = match request.method() {
+        Method::Post => reqwest::Method::POST,
+        _ => reqwest::Method::GET,
+    };
+    Some(Signature {
+        message: endpoints::signed_message(&method, path, &timestamp, body),
+        signature,
+    })
+}
+
+fn route(state: &mut State, path: &str, body: &str, signature: Option<&Signature>) -> Reply {
+    match path {
+        "key" => with_params(body, |params| join(state, params, signature)),
+        "ready" => with_params(body, |params| ack(state, params, signature)),
+        "part" => with_params(body, |params| participants(state, params)),
+        "del" => with_params(body, |params| delete(state, params, signature)),
+        _ => error(404, None),
+    }
+}
+
+fn events(state: &mut State) -> Response<std::io::Cursor<Vec<u8>>> {
+    let events = match state.events.pop_front() {
+        Some(events) => events,
+        None => return to_response(error(404, None)),
+    };
+    let body: String = events
+        .iter()
+        .map(|event| format!("data: {}\n\n", json!(event)))
+        .collect();
+    Response::from_string(body).with_header(header("Content-Type", "text/event-stream"))
+}
+
+fn is_signed_by(signature: Option<&Signature>, public_key: &str) -> bool {
+    matches!(signature, Some(signature) if signature.is_valid(public_key))
+}
+
+fn join(
+    state: &mut State,
+    params: SessionKeyRequestParams,
+    signature: Option<&Signature>,
+) -> Reply {
+    // Proves that the peer has the private key of the posted key
+    if !is_signed_by(signature, &params.key) {
+        return error(401, Some("invalid_signature"));
+    }
+    let session = state.sessions.entry(params.session_id).or_default();
+    if !session.keys.contains(&params.key) {
+        if session.keys.len() >= 2 {
+            return error(400, Some("session_full"));
+        }
+        session.keys.push(params.key);
+    }
+    success(JoinSessionResult {
+        keys: to_public_keys(&session.keys),
+    })
+}
+
+fn ack(state: &mut State, params: AckRequestParams, signature: Option<&Signature>) -> Reply {
+    let session = match find_by_peer_id(state, &params.uuid) {
+        Some(session) => session,
+        None => return error(404, Some("session_not_found")),
+    };
+    if !is_signed_by(signature, peer_key(session, &params.uuid)) {
+        return error(401, Some("invalid_signature"));
+    }
+    match session.acks.get(&params.uuid) {
+        // The ack can't be changed
+        Some(accepted) if *accepted != params.accepted => {
+            return error(400, Some("already_acked"));
+        }
+        _ => {}
+    }
+    session.acks.insert(params.uuid, params.accepted);
+    let is_ready = session.keys.len() == 2
+        && session
+            .keys
+            .iter()
+            .all(|key| session.acks.get(&MockBackend::peer_id(key)) == Some(&2));
+    success(AckSessionResult { is_ready })
+}
+
+// Like the real backend, unknown (or deleted) sessions have no keys
+fn participants(state: &mut State, params: ParticipantsRequestParams) -> Reply {
+    let keys = state
+        .sessions
+        .get(&params.session_id)
+        .map(|session| to_public_keys(&session.keys))
+        .unwrap_or_default();
+    success(ParticipantsResult { keys })
+}
+
+// The session is removed when all the peers marked it as deleted.
+fn delete(
+    state: &mut State,
+    params: PeerDeleteSesionParams,
+    signature: Option<&Signature>,
+) -> Reply {
+    let session_id = match state
+        .sessions
+        .iter()
+        .find(|(_, session)| has_peer(session, &params.peer_id))
+        .map(|(id, _)| id.clone())
+    {
+        Some(session_id) => session_id,
+        None => return error(404, Some("session_not_found")),
+    };
+
+    let session = state.sessions.get_mut(&session_id).unwrap();
+    if !is_signed_by(signature, peer_key(session, &params.peer_id)) {
+        return error(401, Some("invalid_signature"));
+    }
+    if !session.deleted.contains(&params.peer_id) {
+        session.deleted.push(params.peer_id);
+    }
+    if session.deleted.len() == session.keys.len() {
+        state.sessions.remove(&session_id);
+    }
+    (200, json!({ "status": "success" }))
+}
+
+fn find_by_peer_id<'a>(state: &'a mut State, peer_id: &str) -> Option<&'a mut MockSession> {
+    state
+        .sessions
+        .values_mut()
+        .find(|session| has_peer(session, peer_id))
+}
+
+fn has_peer(session: &MockSession, peer_id: &str) -> bool {
+    session
+        .keys
+        .iter()
+        .any(|key| MockBackend::peer_id(key) == peer_id)
+}
+
+// The session has to have the peer (see has_peer)
+fn peer_key<'a>(session: &'a MockSession, peer_id: &str) -> &'a str {
+    session
+        .keys
+        .iter()
+        .find(|key| MockBackend::peer_id(key) == peer_id)
+        .unwrap()
+}
+
+fn with_params<T, F>(body: &str, handler: F) -> Reply
+where
+    T: DeserializeOwned,
+    F: FnOnce(T) -> Reply,
+{
+    match serde_json::from_str(body) {
+        Ok(params) => handler(params),
+        Err(_) => error(400, Some("invalid_params")),
+    }
+}
+
+fn to_public_keys(keys: &[String]) -> Vec<PublicKey> {
+    keys.iter()
+        .map(|key| PublicKey { str: key.clone() })
+        .collect()
+}
+
+fn success<T: Serialize>(payload: T) -> Reply {
+    (200, json!({ "status": "success", "payload": payload }))
+}
+
+fn error(status: u16, code: Option<&str>) -> Reply {
+    (
+        status,
+        json!({
+            "status": "error",
+            "error": { "code": code, "message": format!("Mock error, status: {}", status) }
+        }),
+    )
+}
+
+fn to_response((status, body): Reply) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("Invalid header")
+}
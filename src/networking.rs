@@ -1,26 +1,26 @@
-use std::time::Duration;
-
-use crate::globals::ClientSessionKey;
-use backoff::{ExponentialBackoff, Operation};
+use crate::{
+    config::CoreConfig,
+    crypto::PrivateKey,
+    endpoints::{self, Ack, Delete, Endpoint, JoinSession, Participants},
+    errors::CoreError,
+    globals::ClientSessionKey,
+    retry::{NextAction, Retrier},
+};
 use log::*;
 use ploc_common::{
     errors::{NetworkingError, UNKNOWN_HTTP_STATUS},
     model_types::PublicKey,
     networking_types::{
-        AckRequestParams, AckSessionResult, HttpError, JoinSessionResult,
-        ParticipantsRequestParams, ParticipantsResult, PeerDeleteSesionParams,
+        AckRequestParams, ParticipantsRequestParams, PeerDeleteSesionParams,
         SessionKeyRequestParams,
     },
 };
 use reqwest::{
-    blocking::{Client, Response},
-    Error,
+    blocking::{Client, RequestBuilder, Response},
+    Error, StatusCode,
 };
-use serde::{de::DeserializeOwned, Serialize};
-
-static BASE_URL: &str = "http://192.168.0.123:8000/";
-// static BASE_URL: &str = "http://127.0.0.1:8000/";
-// static BASE_URL: &str = "http://localhost.charlesproxy.com:8000/";
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{thread, time::Instant};
 
 #[derive(Debug, Serialize)]
 pub struct Session {
@@ -28,19 +28,104 @@ pub struct Session {
     pub keys: Vec<PublicKey>,
 }
 
+// Wraps all backend responses. Errors carry an optional machine-readable code (see ERROR_CODE_*).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseEnvelope<T> {
+    pub status: ResponseStatus,
+    pub error: Option<ResponseError>,
+    pub payload: Option<T>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseStatus {
+    Success,
+    Error,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseError {
+    pub code: Option<String>,
+    pub message: Option<String>,
+}
+
+pub const ERROR_CODE_SESSION_NOT_FOUND: &str = "session_not_found";
+pub const ERROR_CODE_SESSION_FULL: &str = "session_full";
+pub const ERROR_CODE_ALREADY_ACKED: &str = "already_acked";
+
+fn to_core_error(http_status: u16, error: Option<ResponseError>) -> CoreError {
+    let code = error.as_ref().and_then(|e| e.code.as_deref());
+    match code {
+        Some(ERROR_CODE_SESSION_NOT_FOUND) => CoreError::SessionNotFound,
+        Some(ERROR_CODE_SESSION_FULL) => CoreError::SessionFull,
+        Some(ERROR_CODE_ALREADY_ACKED) => CoreError::AlreadyAcked,
+        _ => CoreError::Networking(NetworkingError {
+            http_status,
+            message: format!("Http error response: {:?}", error),
+        }),
+    }
+}
+
+pub fn parse_payload<T>(status: StatusCode, body: &[u8]) -> Result<Option<T>, CoreError>
+where
+    T: DeserializeOwned + std::fmt::Debug,
+{
+    let res = serde_json::from_slice::<ResponseEnvelope<T>>(body);
+    info!("Http response parsing result: {:?}", res);
+
+    match res {
+        Ok(envelope) => match envelope.status {
+            ResponseStatus::Success if status.is_success() => Ok(envelope.payload),
+            _ => Err(to_core_error(status.as_u16(), envelope.error)),
+        },
+        Err(error) => Err(CoreError::Networking(NetworkingError {
+            http_status: if status.is_success() {
+                UNKNOWN_HTTP_STATUS
+            } else {
+                status.as_u16()
+            },
+            message: format!("Invalid http response: {:?}", error),
+        })),
+    }
+}
+
+pub fn required_payload<T>(payload: Option<T>) -> Result<T, CoreError> {
+    payload.ok_or_else(|| {
+        CoreError::Networking(NetworkingError {
+            http_status: UNKNOWN_HTTP_STATUS,
+            message: "Http success response without payload".to_owned(),
+        })
+    })
+}
+
+// private_key: PEM of the session's key pair, signs the requests that mutate state
 pub trait RemoteSessionApi {
-    fn join_session(&self, session_key: ClientSessionKey) -> Result<Session, NetworkingError>;
-    fn ack(&self, uuid: String, count: i32) -> Result<bool, NetworkingError>;
-    fn participants(&self, session_id: String) -> Result<Session, NetworkingError>;
-    fn delete(&self, peer_id: String) -> Result<(), NetworkingError>;
+    fn join_session(
+        &self,
+        session_key: ClientSessionKey,
+        private_key: &PrivateKey,
+    ) -> Result<Session, CoreError>;
+    fn ack(&self, uuid: String, count: i32, private_key: &PrivateKey) -> Result<bool, CoreError>;
+    fn participants(&self, session_id: String) -> Result<Session, CoreError>;
+    fn delete(&self, peer_id: String, private_key: &PrivateKey) -> Result<(), CoreError>;
 }
 
-pub struct RemoteSessionApiImpl {}
+pub struct RemoteSessionApiImpl {
+    config: CoreConfig,
+    // Created once and reused for all requests (connection pooling, keep-alive, TLS session reuse)
+    client: Client,
+}
 
 impl RemoteSessionApiImpl {
-    // TODO is it bad to create a new client per request? should we cache it?
-    fn create_client() -> Result<Client, Error> {
+    pub fn new(config: CoreConfig) -> Result<RemoteSessionApiImpl, NetworkingError> {
+        let client = Self::create_client(&config)?;
+        Ok(RemoteSessionApiImpl { config, client })
+    }
+
+    fn create_client(config: &CoreConfig) -> Result<Client, Error> {
         reqwest::blocking::Client::builder()
+            .connect_timeout(config.connect_timeout())
+            .timeout(config.request_timeout())
             // .proxy(reqwest::Proxy::https("http://localhost:8888")?) // Charles proxy
             // .proxy(reqwest::Proxy::https("http://192.168.0.2:8888")?) // Charles proxy
             // .proxy(reqwest::Proxy::https(
@@ -49,75 +134,109 @@ impl RemoteSessionApiImpl {
             .build()
     }
 
-    fn deserialize<T>(response: Response) -> Result<T, NetworkingError>
+    fn deserialize<T>(response: Response) -> Result<T, CoreError>
+    where
+        T: DeserializeOwned + std::fmt::Debug,
+    {
+        required_payload(Self::payload(response)?)
+    }
+
+    fn payload<T>(response: Response) -> Result<Option<T>, CoreError>
     where
         T: DeserializeOwned + std::fmt::Debug,
     {
         let status = response.status();
-        if status.is_success() {
-            let res = response.json::<T>();
-            info!("Http response parsing result: {:?}", res);
+        let body = response.bytes()?;
+        parse_payload(status, &body)
+    }
 
-            match res {
-                Ok(obj) => Ok(obj),
-                Err(error) => Err(NetworkingError {
-                    http_status: UNKNOWN_HTTP_STATUS,
-                    message: format!("Http internal error response: {:?}", error),
-                }),
-            }
-        } else {
-            let res = response.json::<HttpError>();
-            info!("Http error response parsing result: {:?}", res);
+    // Sends a request to the endpoint and returns its payload.
+    // private_key is needed only for signed endpoints (see endpoints::prepare).
+    fn call<E: Endpoint>(
+        &self,
+        params: &E::Request,
+        private_key: Option<&PrivateKey>,
+    ) -> Result<E::Response, CoreError> {
+        Self::deserialize(self.request::<E>(params, private_key)?)
+    }
 
-            Err(NetworkingError {
-                http_status: status.as_u16(),
-                message: format!("Http error response: {:?}", res),
-            })
-        }
+    // For endpoints whose success response has no payload
+    fn call_without_payload<E: Endpoint>(
+        &self,
+        params: &E::Request,
+        private_key: Option<&PrivateKey>,
+    ) -> Result<(), CoreError> {
+        Self::payload::<E::Response>(self.request::<E>(params, private_key)?).map(|_| ())
+    }
+
+    // All requests go through here: common headers, signatures, logging, retries.
+    fn request<E: Endpoint>(
+        &self,
+        params: &E::Request,
+        private_key: Option<&PrivateKey>,
+    ) -> Result<Response, CoreError> {
+        let request = endpoints::prepare::<E>(&self.config, params, private_key)?;
+
+        let start = Instant::now();
+        let res = self.send(|| {
+            self.client
+                .request(request.method.clone(), &request.url)
+                .headers(request.headers.clone())
+                .body(request.body.clone())
+        });
+        debug!(
+            "Networking: {} {} took {:?}, status: {:?}",
+            request.method,
+            request.url,
+            start.elapsed(),
+            res.as_ref().map(|r| r.status())
+        );
+        res
     }
 
-    fn backoff() -> ExponentialBackoff {
-        ExponentialBackoff {
-            max_elapsed_time: Some(Duration::new(5, 0)),
-            ..ExponentialBackoff::default()
+    // Sends the request, retrying on transient failures (see retry.rs).
+    // Non retryable responses (e.g. 4xx) are returned, to be parsed by the caller.
+    fn send<F>(&self, request: F) -> Result<Response, CoreError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut retrier = Retrier::new(&self.config.retry);
+        loop {
+            match retrier.next_action(request().send()) {
+                NextAction::Complete(response) => return Ok(response),
+                NextAction::Fail(error) => return Err(error),
+                NextAction::Retry(delay) => thread::sleep(delay),
+            }
         }
     }
 }
 
 impl RemoteSessionApi for RemoteSessionApiImpl {
-    fn join_session(&self, session_key: ClientSessionKey) -> Result<Session, NetworkingError> {
+    fn join_session(
+        &self,
+        session_key: ClientSessionKey,
+        private_key: &PrivateKey,
+    ) -> Result<Session, CoreError> {
         info!("Networking: joining session, key: {:?}", session_key);
 
         let params = SessionKeyRequestParams {
-            session_id: session_key.session_id.clone(),
-            key: session_key.clone().key.str,
-        };
-
-        let url: &str = &format!("{}key", BASE_URL.to_owned())[..];
-        let params_str = serde_json::to_string(&params).unwrap();
-
-        let client = Self::create_client()?;
-
-        let mut op = || -> Result<Response, backoff::Error<NetworkingError>> {
-            let response = client
-                .post(url)
-                .header("Content-Type", "application/json")
-                .body(params_str.clone())
-                .send()
-                .map_err(NetworkingError::from)?;
-
-            Ok(response)
+            session_id: session_key.session_id,
+            key: session_key.key.str,
         };
 
-        let response = op.retry(&mut Self::backoff()).map_err(|e| e.error())?;
-
-        RemoteSessionApiImpl::deserialize(response).map(|r: JoinSessionResult| Session {
-            id: session_key.session_id,
-            keys: r.keys,
-        })
+        self.call::<JoinSession>(&params, Some(private_key))
+            .map(|r| Session {
+                id: params.session_id,
+                keys: r.keys,
+            })
     }
 
-    fn ack(&self, uuid: String, stored_participants: i32) -> Result<bool, NetworkingError> {
+    fn ack(
+        &self,
+        uuid: String,
+        stored_participants: i32,
+        private_key: &PrivateKey,
+    ) -> Result<bool, CoreError> {
         info!(
             "Networking: ack-ing session for: {:?}, participants: {:?}",
             uuid, stored_participants
@@ -128,34 +247,11 @@ impl RemoteSessionApi for RemoteSessionApiImpl {
             accepted: stored_participants,
         };
 
-        let url: &str = &format!("{}ready", BASE_URL.to_owned())[..];
-        let params_str = serde_json::to_string(&params).unwrap();
-
-        let client = Self::create_client()?;
-
-        let mut op = || -> Result<Response, backoff::Error<NetworkingError>> {
-            let response = client
-                .post(url)
-                .header("Content-Type", "application/json")
-                .body(params_str.clone())
-                .send()
-                .map_err(NetworkingError::from)?;
-
-            Ok(response)
-        };
-        let response = op.retry(&mut Self::backoff()).map_err(|e| e.error())?;
-
-        // TODO improve response handling: generic status: i32, err: string?, success: T?:  (?)
-        // currently if JSON response isn't success, parsing to success type fails and does early exit
-        // this works but not quite as intended: we want to handle success/error JSON payloads in the match below
-        // currently app error messages are confusing, as we get parser errors, not the actual error
-        // the other requests also have this problem.
-        debug!("Ack-ed, networking response: {:?}", response);
-
-        RemoteSessionApiImpl::deserialize(response).map(|r: AckSessionResult| r.is_ready)
+        self.call::<Ack>(&params, Some(private_key))
+            .map(|r| r.is_ready)
     }
 
-    fn participants(&self, session_id: String) -> Result<Session, NetworkingError> {
+    fn participants(&self, session_id: String) -> Result<Session, CoreError> {
         info!(
             "Networking: requesting participants, session id: {:?}",
             session_id
@@ -165,174 +261,364 @@ impl RemoteSessionApi for RemoteSessionApiImpl {
             session_id: session_id.clone(),
         };
 
-        let url: &str = &format!("{}part", BASE_URL.to_owned())[..];
-        let params_str = serde_json::to_string(&params).unwrap();
-
-        let client = Self::create_client()?;
-
-        let mut op = || -> Result<Response, backoff::Error<NetworkingError>> {
-            let response = client
-                .post(url)
-                .header("Content-Type", "application/json")
-                .body(params_str.clone())
-                .send()
-                .map_err(NetworkingError::from)?;
-
-            Ok(response)
-        };
-        let response = op.retry(&mut Self::backoff()).map_err(|e| e.error())?;
-
-        RemoteSessionApiImpl::deserialize(response).map(|r: ParticipantsResult| Session {
+        self.call::<Participants>(&params, None).map(|r| Session {
             id: session_id,
             keys: r.keys,
         })
     }
 
-    fn delete(&self, peer_id: String) -> Result<(), NetworkingError> {
+    fn delete(&self, peer_id: String, private_key: &PrivateKey) -> Result<(), CoreError> {
         info!("Networking: marking as deleted, peer id: {:?}", peer_id);
 
-        let params = PeerDeleteSesionParams {
-            peer_id: peer_id.clone(),
-        };
+        let params = PeerDeleteSesionParams { peer_id };
 
-        let url: &str = &format!("{}del", BASE_URL.to_owned())[..];
-        let params_str = serde_json::to_string(&params).unwrap();
+        self.call_without_payload::<Delete>(&params, Some(private_key))
+    }
+}
 
-        let client = Self::create_client()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto,
+        globals::{ClientSessionKey, KeyPair},
+        mock_backend::MockBackend,
+    };
+    use ploc_common::{model_types::PublicKey, networking_types::AckSessionResult};
+    use std::time::Duration;
+    use uuid::Uuid;
 
-        let mut op = || -> Result<Response, backoff::Error<NetworkingError>> {
-            let response = client
-                .post(url)
-                .header("Content-Type", "application/json")
-                .body(params_str.clone())
-                .send()
-                .map_err(NetworkingError::from)?;
+    fn key_pair() -> KeyPair {
+        crypto::create_key_pair().unwrap()
+    }
 
-            Ok(response)
-        };
-        op.retry(&mut Self::backoff()).map_err(|e| e.error())?;
+    fn join(
+        api: &RemoteSessionApiImpl,
+        session_id: &str,
+        key_pair: &KeyPair,
+    ) -> Result<Session, CoreError> {
+        api.join_session(
+            ClientSessionKey {
+                session_id: session_id.to_owned(),
+                key: PublicKey {
+                    str: key_pair.public.clone(),
+                },
+            },
+            &key_pair.private,
+        )
+    }
 
-        Ok(())
+    fn ack(api: &RemoteSessionApiImpl, key_pair: &KeyPair) -> Result<bool, CoreError> {
+        api.ack(MockBackend::peer_id(&key_pair.public), 2, &key_pair.private)
     }
-}
 
-#[cfg(test)]
-mod tests {
+    fn delete(api: &RemoteSessionApiImpl, key_pair: &KeyPair) -> Result<(), CoreError> {
+        api.delete(MockBackend::peer_id(&key_pair.public), &key_pair.private)
+    }
 
-    // TODO remove these tests
-    // the db in the server of course doesn't get cleared between tests,
-    // so state accumulates between tests. Current tests fail when run together.
+    #[test]
+    fn parses_success_envelope() {
+        let json = r#"{"status": "success", "error": null, "payload": {"is_ready": true}}"#;
+        let res = serde_json::from_str::<ResponseEnvelope<AckSessionResult>>(json);
+
+        assert!(res.is_ok());
+        let envelope = res.unwrap();
+        assert_eq!(envelope.status, ResponseStatus::Success);
+        assert!(envelope.payload.unwrap().is_ready);
+    }
+
+    #[test]
+    fn maps_known_error_codes() {
+        let json = r#"{"status": "error", "error": {"code": "session_full", "message": "Session has 2 participants"}}"#;
+        let envelope = serde_json::from_str::<ResponseEnvelope<AckSessionResult>>(json).unwrap();
+
+        let error = to_core_error(400, envelope.error);
+        assert!(matches!(error, CoreError::SessionFull));
+    }
+
+    #[test]
+    fn maps_unknown_error_code_to_networking_error() {
+        let error = to_core_error(
+            500,
+            Some(ResponseError {
+                code: Some("db_error".to_owned()),
+                message: None,
+            }),
+        );
+        match error {
+            CoreError::Networking(e) => assert_eq!(e.http_status, 500),
+            _ => panic!("Unexpected error: {:?}", error),
+        }
+    }
 
-    use super::*;
-    use crate::globals::ClientSessionKey;
-    use ploc_common::model_types::PublicKey;
-    use uuid::Uuid;
-    //To run these tests use: 'cargo test -- --ignored'
     #[test]
-    #[ignore]
     fn start_session_is_ok() {
-        let api = RemoteSessionApiImpl {};
-        let res = api.join_session(ClientSessionKey {
-            session_id: "1".to_owned(),
-            key: PublicKey {
-                str: "2".to_owned(),
-            },
-        });
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let key_pair = key_pair();
+
+        let res = join(&api, "1", &key_pair);
 
         assert!(res.is_ok());
 
         let session = res.unwrap();
         assert_eq!(session.id, "1");
         assert_eq!(session.keys.len(), 1);
-        assert_eq!(session.keys[0].str, "2");
+        assert_eq!(session.keys[0].str, key_pair.public);
     }
 
     #[test]
-    #[ignore]
     fn start_and_join_session_is_ok() {
-        let api = RemoteSessionApiImpl {};
-        let res1 = api.join_session(ClientSessionKey {
-            session_id: "1".to_owned(),
-            key: PublicKey {
-                str: "2".to_owned(),
-            },
-        });
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let (key_pair1, key_pair2) = (key_pair(), key_pair());
+
+        let res1 = join(&api, "1", &key_pair1);
 
         assert!(res1.is_ok());
 
         let session1 = res1.unwrap();
         assert_eq!(session1.id, "1");
         assert_eq!(session1.keys.len(), 1);
-        assert_eq!(session1.keys[0].str, "2");
+        assert_eq!(session1.keys[0].str, key_pair1.public);
 
-        let res2 = api.join_session(ClientSessionKey {
-            session_id: "1".to_owned(),
-            key: PublicKey {
-                str: "3".to_owned(),
-            },
-        });
+        let res2 = join(&api, "1", &key_pair2);
 
         let session2 = res2.unwrap();
         assert_eq!(session2.keys.len(), 2);
-        assert_eq!(session2.keys[0].str, "2");
-        assert_eq!(session2.keys[1].str, "3");
+        assert_eq!(session2.keys[0].str, key_pair1.public);
+        assert_eq!(session2.keys[1].str, key_pair2.public);
     }
 
     #[test]
-    #[ignore]
     fn sessions_are_separate() {
-        let api = RemoteSessionApiImpl {};
-        let res1 = api.join_session(ClientSessionKey {
-            session_id: "1".to_owned(),
-            key: PublicKey {
-                str: "2".to_owned(),
-            },
-        });
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let (key_pair1, key_pair2) = (key_pair(), key_pair());
+
+        let res1 = join(&api, "1", &key_pair1);
 
         assert!(res1.is_ok());
 
         let session1 = res1.unwrap();
         assert_eq!(session1.keys.len(), 1);
-        assert_eq!(session1.keys[0].str, "2");
+        assert_eq!(session1.keys[0].str, key_pair1.public);
 
-        let res2 = api.join_session(ClientSessionKey {
-            session_id: "100".to_owned(),
-            key: PublicKey {
-                str: "3".to_owned(),
-            },
-        });
+        let res2 = join(&api, "100", &key_pair2);
 
         let session2 = res2.unwrap();
         assert_eq!(session2.keys.len(), 1);
-        assert_eq!(session2.keys[0].str, "3");
+        assert_eq!(session2.keys[0].str, key_pair2.public);
     }
 
     #[test]
-    #[ignore]
     fn ack_session_is_err() {
-        let api = RemoteSessionApiImpl {};
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
         // random uuid, so it will not find anything
-        let res1 = api.ack(Uuid::new_v4().to_string(), 1);
-        assert!(res1.is_err());
+        let res1 = api.ack(Uuid::new_v4().to_string(), 1, &key_pair().private);
+        assert!(matches!(res1, Err(CoreError::SessionNotFound)));
     }
 
     #[test]
-    #[ignore]
     fn participants_is_ok() {
-        let api = RemoteSessionApiImpl {};
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
         let res1 = api.participants("123".to_owned());
         assert!(res1.is_ok());
     }
-}
 
-trait WithWrappedError<E> {
-    fn error(self) -> E;
-}
+    #[test]
+    fn participants_are_the_joined_keys() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let key_pair = key_pair();
+        join(&api, "123", &key_pair).unwrap();
+
+        let res = api.participants("123".to_owned());
+        assert_eq!(res.unwrap().keys[0].str, key_pair.public);
+    }
+
+    #[test]
+    fn unknown_session_has_no_participants() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+
+        let res = api.participants("123".to_owned());
+        assert!(res.unwrap().keys.is_empty());
+    }
+
+    #[test]
+    fn third_participant_is_rejected() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        join(&api, "1", &key_pair()).unwrap();
+        join(&api, "1", &key_pair()).unwrap();
+
+        let res = join(&api, "1", &key_pair());
+        assert!(matches!(res, Err(CoreError::SessionFull)));
+    }
+
+    #[test]
+    fn session_is_ready_when_both_peers_acked() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let (key_pair1, key_pair2) = (key_pair(), key_pair());
+        join(&api, "1", &key_pair1).unwrap();
+        join(&api, "1", &key_pair2).unwrap();
+
+        assert!(!ack(&api, &key_pair1).unwrap());
+        assert!(ack(&api, &key_pair2).unwrap());
+    }
+
+    #[test]
+    fn acking_again_returns_readiness() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let (key_pair1, key_pair2) = (key_pair(), key_pair());
+        join(&api, "1", &key_pair1).unwrap();
+        join(&api, "1", &key_pair2).unwrap();
+
+        assert!(!ack(&api, &key_pair1).unwrap());
+        assert!(!ack(&api, &key_pair1).unwrap());
+        assert!(ack(&api, &key_pair2).unwrap());
+        assert!(ack(&api, &key_pair1).unwrap());
+    }
+
+    #[test]
+    fn changing_the_ack_is_already_acked() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let key_pair = key_pair();
+        join(&api, "1", &key_pair).unwrap();
+        let peer_id = MockBackend::peer_id(&key_pair.public);
+
+        api.ack(peer_id.clone(), 1, &key_pair.private).unwrap();
+        let res = api.ack(peer_id, 2, &key_pair.private);
+
+        assert!(matches!(res, Err(CoreError::AlreadyAcked)));
+    }
+
+    #[test]
+    fn session_is_removed_when_both_peers_deleted() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let (key_pair1, key_pair2) = (key_pair(), key_pair());
+        join(&api, "1", &key_pair1).unwrap();
+        join(&api, "1", &key_pair2).unwrap();
+
+        assert!(delete(&api, &key_pair1).is_ok());
+        assert!(api.participants("1".to_owned()).is_ok());
+        assert!(delete(&api, &key_pair2).is_ok());
+
+        let res = api.participants("1".to_owned());
+        assert!(res.unwrap().keys.is_empty());
+    }
+
+    #[test]
+    fn rejects_requests_signed_with_other_key() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let (key_pair, other) = (key_pair(), key_pair());
+        join(&api, "1", &key_pair).unwrap();
+        let peer_id = MockBackend::peer_id(&key_pair.public);
+
+        // E.g. someone who knows the peer id, acking / deleting on the peer's behalf
+        let ack_res = api.ack(peer_id.clone(), 2, &other.private);
+        let delete_res = api.delete(peer_id, &other.private);
+        // Posting a key without having its private key
+        let join_res = api.join_session(
+            ClientSessionKey {
+                session_id: "2".to_owned(),
+                key: PublicKey {
+                    str: key_pair.public.clone(),
+                },
+            },
+            &other.private,
+        );
 
-impl<E> WithWrappedError<E> for backoff::Error<E> {
-    fn error(self) -> E {
-        match self {
-            backoff::Error::Permanent(err) | backoff::Error::Transient(err) => err,
+        for res in &[ack_res.map(|_| ()), delete_res, join_res.map(|_| ())] {
+            match res {
+                Err(CoreError::Networking(e)) => assert_eq!(e.http_status, 401),
+                _ => panic!("Unexpected result: {:?}", res),
+            }
         }
     }
+
+    #[test]
+    fn retries_server_errors() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        backend.fail_next("key", 503);
+        backend.fail_next("key", 500);
+
+        let res = join(&api, "1", &key_pair());
+
+        assert!(res.is_ok());
+        assert_eq!(backend.requests("key"), 3);
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        backend.fail_next_with_code("key", 400, "session_full");
+
+        let res = join(&api, "1", &key_pair());
+
+        assert!(matches!(res, Err(CoreError::SessionFull)));
+        assert_eq!(backend.requests("key"), 1);
+    }
+
+    #[test]
+    fn unknown_error_code_is_networking_error() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        backend.fail_next("part", 400);
+
+        let res = api.participants("1".to_owned());
+
+        match res {
+            Err(CoreError::Networking(e)) => assert_eq!(e.http_status, 400),
+            _ => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn gives_up_after_max_elapsed_time() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        for _ in 0..100 {
+            backend.fail_next("part", 503);
+        }
+
+        let res = api.participants("1".to_owned());
+
+        match res {
+            Err(CoreError::RetriesExhausted {
+                attempts,
+                last_error,
+            }) => {
+                assert!(attempts > 1);
+                assert_eq!(attempts as usize, backend.requests("part"));
+                assert_eq!(last_error.http_status, 503);
+            }
+            _ => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn gives_up_if_retry_after_is_too_long() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        backend.fail_next_with_retry_after("part", 429, Duration::from_secs(60));
+
+        let res = api.participants("1".to_owned());
+
+        assert!(matches!(
+            res,
+            Err(CoreError::RetriesExhausted { attempts: 1, .. })
+        ));
+        assert_eq!(backend.requests("part"), 1);
+    }
 }
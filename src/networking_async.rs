@@ -0,0 +1,250 @@
+use crate::{
+    config::CoreConfig,
+    crypto::PrivateKey,
+    endpoints::{self, Ack, Delete, Endpoint, JoinSession, Participants},
+    errors::CoreError,
+    globals::ClientSessionKey,
+    networking::{parse_payload, required_payload, Session},
+    retry::{NextAction, Retrier},
+};
+use async_trait::async_trait;
+use log::*;
+use ploc_common::networking_types::{
+    AckRequestParams, ParticipantsRequestParams, PeerDeleteSesionParams, SessionKeyRequestParams,
+};
+use reqwest::{Client, Response};
+use std::time::Instant;
+use tokio::time::delay_for;
+
+// Async counterpart of RemoteSessionApi. Runs on the core's runtime (see globals).
+#[async_trait]
+pub trait AsyncRemoteSessionApi {
+    async fn join_session(
+        &self,
+        session_key: ClientSessionKey,
+        private_key: &PrivateKey,
+    ) -> Result<Session, CoreError>;
+    async fn ack(
+        &self,
+        uuid: String,
+        count: i32,
+        private_key: &PrivateKey,
+    ) -> Result<bool, CoreError>;
+    async fn participants(&self, session_id: String) -> Result<Session, CoreError>;
+    async fn delete(&self, peer_id: String, private_key: &PrivateKey) -> Result<(), CoreError>;
+}
+
+pub struct AsyncRemoteSessionApiImpl {
+    config: CoreConfig,
+    client: Client,
+}
+
+impl AsyncRemoteSessionApiImpl {
+    pub fn new(config: CoreConfig) -> Result<AsyncRemoteSessionApiImpl, CoreError> {
+        let client = Client::builder()
+            .connect_timeout(config.connect_timeout())
+            .timeout(config.request_timeout())
+            .build()?;
+        Ok(AsyncRemoteSessionApiImpl { config, client })
+    }
+
+    async fn call<E: Endpoint>(
+        &self,
+        params: &E::Request,
+        private_key: Option<&PrivateKey>,
+    ) -> Result<E::Response, CoreError> {
+        required_payload(self.payload::<E>(params, private_key).await?)
+    }
+
+    async fn call_without_payload<E: Endpoint>(
+        &self,
+        params: &E::Request,
+        private_key: Option<&PrivateKey>,
+    ) -> Result<(), CoreError> {
+        self.payload::<E>(params, private_key).await.map(|_| ())
+    }
+
+    async fn payload<E: Endpoint>(
+        &self,
+        params: &E::Request,
+        private_key: Option<&PrivateKey>,
+    ) -> Result<Option<E::Response>, CoreError> {
+        let request = endpoints::prepare::<E>(&self.config, params, private_key)?;
+
+        let start = Instant::now();
+        let res = self
+            .send(|| {
+                self.client
+                    .request(request.method.clone(), &request.url)
+                    .headers(request.headers.clone())
+                    .body(request.body.clone())
+            })
+            .await;
+        debug!(
+            "Networking (async): {} {} took {:?}, status: {:?}",
+            request.method,
+            request.url,
+            start.elapsed(),
+            res.as_ref().map(|r| r.status())
+        );
+
+        let response = res?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        parse_payload(status, &body)
+    }
+
+    // Same retry handling as the blocking api, but waiting without blocking the thread.
+    async fn send<F>(&self, request: F) -> Result<Response, CoreError>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let mut retrier = Retrier::new(&self.config.retry);
+        loop {
+            match retrier.next_action(request().send().await) {
+                NextAction::Complete(response) => return Ok(response),
+                NextAction::Fail(error) => return Err(error),
+                NextAction::Retry(delay) => delay_for(delay).await,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncRemoteSessionApi for AsyncRemoteSessionApiImpl {
+    async fn join_session(
+        &self,
+        session_key: ClientSessionKey,
+        private_key: &PrivateKey,
+    ) -> Result<Session, CoreError> {
+        info!(
+            "Networking (async): joining session, key: {:?}",
+            session_key
+        );
+
+        let params = SessionKeyRequestParams {
+            session_id: session_key.session_id,
+            key: session_key.key.str,
+        };
+
+        self.call::<JoinSession>(&params, Some(private_key))
+            .await
+            .map(|r| Session {
+                id: params.session_id,
+                keys: r.keys,
+            })
+    }
+
+    async fn ack(
+        &self,
+        uuid: String,
+        stored_participants: i32,
+        private_key: &PrivateKey,
+    ) -> Result<bool, CoreError> {
+        info!(
+            "Networking (async): ack-ing session for: {:?}, participants: {:?}",
+            uuid, stored_participants
+        );
+
+        let params = AckRequestParams {
+            uuid,
+            accepted: stored_participants,
+        };
+
+        self.call::<Ack>(&params, Some(private_key))
+            .await
+            .map(|r| r.is_ready)
+    }
+
+    async fn participants(&self, session_id: String) -> Result<Session, CoreError> {
+        info!(
+            "Networking (async): requesting participants, session id: {:?}",
+            session_id
+        );
+
+        let params = ParticipantsRequestParams {
+            session_id: session_id.clone(),
+        };
+
+        self.call::<Participants>(&params, None)
+            .await
+            .map(|r| Session {
+                id: session_id,
+                keys: r.keys,
+            })
+    }
+
+    async fn delete(&self, peer_id: String, private_key: &PrivateKey) -> Result<(), CoreError> {
+        info!(
+            "Networking (async): marking as deleted, peer id: {:?}",
+            peer_id
+        );
+
+        let params = PeerDeleteSesionParams { peer_id };
+
+        self.call_without_payload::<Delete>(&params, Some(private_key))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto, mock_backend::MockBackend};
+    use ploc_common::model_types::PublicKey;
+    use tokio::runtime::Runtime;
+
+    fn runtime() -> Runtime {
+        tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn join_and_participants_are_ok() {
+        let backend = MockBackend::start();
+        let mut runtime = runtime();
+        let api = runtime
+            .enter(|| AsyncRemoteSessionApiImpl::new(backend.config()))
+            .unwrap();
+        let key_pair = crypto::create_key_pair().unwrap();
+
+        let (joined, participants) = runtime.block_on(async {
+            let joined = api
+                .join_session(
+                    ClientSessionKey {
+                        session_id: "1".to_owned(),
+                        key: PublicKey {
+                            str: key_pair.public.clone(),
+                        },
+                    },
+                    &key_pair.private,
+                )
+                .await;
+            (joined, api.participants("1".to_owned()).await)
+        });
+
+        assert_eq!(joined.unwrap().keys[0].str, key_pair.public);
+        assert_eq!(participants.unwrap().keys[0].str, key_pair.public);
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let backend = MockBackend::start();
+        let mut runtime = runtime();
+        let api = runtime
+            .enter(|| AsyncRemoteSessionApiImpl::new(backend.config()))
+            .unwrap();
+        backend.fail_next("del", 502);
+        backend.fail_next_with_code("del", 404, "session_not_found");
+
+        let private_key = crypto::create_key_pair().unwrap().private;
+
+        let res = runtime.block_on(api.delete("peer".to_owned(), &private_key));
+
+        assert!(matches!(res, Err(CoreError::SessionNotFound)));
+        assert_eq!(backend.requests("del"), 2);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{config::RetryConfig, errors::CoreError};
+use backoff::{backoff::Backoff, ExponentialBackoff};
+use chrono::{DateTime, Utc};
+use log::*;
+use ploc_common::errors::NetworkingError;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+use std::time::Duration;
+
+// Common interface of the blocking and async responses, to classify them.
+pub trait HttpResponse {
+    fn status(&self) -> StatusCode;
+    fn headers(&self) -> &HeaderMap;
+}
+
+impl HttpResponse for reqwest::blocking::Response {
+    fn status(&self) -> StatusCode {
+        self.status()
+    }
+    fn headers(&self) -> &HeaderMap {
+        self.headers()
+    }
+}
+
+impl HttpResponse for reqwest::Response {
+    fn status(&self) -> StatusCode {
+        self.status()
+    }
+    fn headers(&self) -> &HeaderMap {
+        self.headers()
+    }
+}
+
+enum Attempt<R> {
+    // Success or non retryable status (e.g. 4xx), to be parsed by the caller
+    Completed(R),
+    Transient {
+        error: NetworkingError,
+        retry_after: Option<Duration>,
+    },
+    Failed(CoreError),
+}
+
+fn classify<R: HttpResponse>(result: Result<R, reqwest::Error>) -> Attempt<R> {
+    match result {
+        Ok(response) if !is_retryable_status(response.status()) => Attempt::Completed(response),
+        Ok(response) => Attempt::Transient {
+            error: NetworkingError {
+                http_status: response.status().as_u16(),
+                message: format!("Http error status: {}", response.status()),
+            },
+            retry_after: retry_after(response.headers()),
+        },
+        Err(error) if is_retryable_error(&error) => Attempt::Transient {
+            error: NetworkingError::from(error),
+            retry_after: None,
+        },
+        Err(error) => Attempt::Failed(CoreError::from(error)),
+    }
+}
+
+// Only server side and rate limiting statuses are worth retrying.
+// Other 4xx are permanent: the same request will fail again.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect()
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, Utc::now()))
+}
+
+// Retry-After is either a number of seconds or an http date
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    DateTime::parse_from_rfc2822(value).ok().map(|date| {
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::from_secs(0))
+    })
+}
+
+// What the transports (see networking.rs, networking_async.rs) do after an attempt
+pub enum NextAction<R> {
+    // Success or non retryable status (e.g. 4xx), to be parsed by the caller
+    Complete(R),
+    Fail(CoreError),
+    // Wait for the delay and send the request again
+    Retry(Duration),
+}
+
+// Tracks attempts and determines the delay before the next one.
+pub struct Retrier {
+    backoff: ExponentialBackoff,
+    attempts: u32,
+}
+
+impl Retrier {
+    pub fn new(config: &RetryConfig) -> Retrier {
+        let mut backoff = config.backoff();
+        backoff.reset();
+        Retrier {
+            backoff,
+            attempts: 0,
+        }
+    }
+
+    // Classifies the result of an attempt (the request was sent once more)
+    pub fn next_action<R: HttpResponse>(
+        &mut self,
+        result: Result<R, reqwest::Error>,
+    ) -> NextAction<R> {
+        self.start_attempt();
+        let (last_error, retry_after) = match classify(result) {
+            Attempt::Completed(response) => return NextAction::Complete(response),
+            Attempt::Failed(error) => return NextAction::Fail(error),
+            Attempt::Transient { error, retry_after } => (error, retry_after),
+        };
+
+        match self.next_delay(retry_after) {
+            Some(delay) => {
+                warn!(
+                    "Request attempt {} failed: {:?}, retrying in {:?}",
+                    self.attempts, last_error, delay
+                );
+                NextAction::Retry(delay)
+            }
+            None => NextAction::Fail(self.exhausted(last_error)),
+        }
+    }
+
+    fn start_attempt(&mut self) {
+        self.attempts += 1;
+    }
+
+    fn exhausted(&self, last_error: NetworkingError) -> CoreError {
+        CoreError::RetriesExhausted {
+            attempts: self.attempts,
+            last_error,
+        }
+    }
+
+    // Delay before the next attempt, None if we should give up.
+    // If the server sent a Retry-After, it's used instead of the backoff interval,
+    // unless waiting that long exceeds the max elapsed time.
+    fn next_delay(&mut self, retry_after: Option<Duration>) -> Option<Duration> {
+        let delay = self.backoff.next_backoff()?;
+        match (retry_after, self.backoff.max_elapsed_time) {
+            (Some(retry_after), Some(max_elapsed))
+                if self.backoff.get_elapsed_time() + retry_after > max_elapsed =>
+            {
+                None
+            }
+            (Some(retry_after), _) => Some(retry_after),
+            (None, _) => Some(delay),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(str: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc2822(str)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn classifies_statuses() {
+        assert!(is_retryable_status(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(StatusCode::OK));
+    }
+
+    #[test]
+    fn parses_retry_after_seconds() {
+        let res = parse_retry_after("3", Utc::now());
+        assert_eq!(res, Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn parses_retry_after_date() {
+        let now = date("Wed, 21 Oct 2020 07:28:00 GMT");
+        let res = parse_retry_after("Wed, 21 Oct 2020 07:28:10 GMT", now);
+        assert_eq!(res, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn retry_after_date_in_the_past_is_zero() {
+        let now = date("Wed, 21 Oct 2020 07:28:00 GMT");
+        let res = parse_retry_after("Wed, 21 Oct 2020 07:27:00 GMT", now);
+        assert_eq!(res, Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn invalid_retry_after_is_ignored() {
+        assert_eq!(parse_retry_after("soon", Utc::now()), None);
+    }
+
+    #[test]
+    fn gives_up_if_retry_after_exceeds_max_elapsed_time() {
+        let mut retrier = Retrier::new(&RetryConfig::default());
+        retrier.start_attempt();
+        assert_eq!(retrier.next_delay(Some(Duration::from_secs(60))), None);
+        assert_eq!(retrier.attempts, 1);
+    }
+
+    #[test]
+    fn uses_retry_after_if_within_max_elapsed_time() {
+        let mut retrier = Retrier::new(&RetryConfig::default());
+        retrier.start_attempt();
+        assert_eq!(
+            retrier.next_delay(Some(Duration::from_secs(1))),
+            Some(Duration::from_secs(1))
+        );
+    }
+}
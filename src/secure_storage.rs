@@ -0,0 +1,218 @@
+// Persistent storage for the data the core needs after a restart, e.g. the client session (see
+// globals::save_session). Secret: the client session contains the private key.
+// The apps implement it with the platform's secure storage (iOS Keychain, Android
+// EncryptedSharedPreferences) through FFI callbacks (see ffi_ios.rs, ffi_android.rs).
+// EncryptedFileStorage is the default, for tests and desktop.
+
+use crate::{
+    crypto::{self, SharedKey},
+    errors::CoreError,
+    key_format,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+// Key value storage. Called from any thread.
+pub trait SecureStorage: Send + Sync {
+    // None if there's no value for the key
+    fn get(&self, key: &str) -> Result<Option<String>, CoreError>;
+    // Replaces the existing value
+    fn set(&self, key: &str, value: &str) -> Result<(), CoreError>;
+    // Ok if there's no value for the key
+    fn remove(&self, key: &str) -> Result<(), CoreError>;
+}
+
+// Determines the file format, so it can be changed later
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const SALT_FILE: &str = "salt";
+
+// One file per key, encrypted with a key derived from a password (see crypto::password_key).
+// The salt is stored in the directory, created when opened the first time.
+//
+// File format: version (1 byte) || nonce (12 bytes) || encrypted value || tag (16 bytes)
+// The version and the key are authenticated as associated data: a file can't be swapped with the
+// file of another key.
+pub struct EncryptedFileStorage {
+    dir: PathBuf,
+    key: SharedKey,
+    // Serializes the writes, which go through a temporary file per key
+    write_lock: Mutex<()>,
+}
+
+impl EncryptedFileStorage {
+    // Creates the directory if it doesn't exist. A wrong password is detected only when reading.
+    pub fn open<P: Into<PathBuf>>(
+        dir: P,
+        password: &str,
+    ) -> Result<EncryptedFileStorage, CoreError> {
+        if password.is_empty() {
+            return Err(CoreError::General("Password is empty".to_owned()));
+        }
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| io_error("Couldn't create storage directory", e))?;
+        let salt = read_or_create_salt(&dir.join(SALT_FILE))?;
+        let key = crypto::password_key(password, &salt)?;
+        Ok(EncryptedFileStorage {
+            dir,
+            key,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    // base64url: a valid file name for any key
+    fn path(&self, key: &str) -> PathBuf {
+        let name = key_format::to_base64url(key.as_bytes());
+        self.dir.join(format!("{}.enc", name))
+    }
+}
+
+impl SecureStorage for EncryptedFileStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, CoreError> {
+        let data = match fs::read(self.path(key)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error("Couldn't read stored value", e)),
+        };
+        match data.first() {
+            Some(&VERSION) => {}
+            version => {
+                return Err(CoreError::General(format!(
+                    "Unsupported stored value version: {:?}",
+                    version
+                )))
+            }
+        }
+
+        let decrypted = crypto::decrypt(&self.key, &data[1..], &aad(key)).ok_or_else(|| {
+            CoreError::General(
+                "Couldn't decrypt stored value: wrong password or modified".to_owned(),
+            )
+        })?;
+        String::from_utf8(decrypted)
+            .map(Some)
+            .map_err(|_| CoreError::General("Stored value isn't utf8".to_owned()))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), CoreError> {
+        let mut data = vec![VERSION];
+        data.extend(crypto::encrypt(&self.key, value.as_bytes(), &aad(key))?);
+
+        let _lock = self.write_lock.lock().expect("Storage lock poisoned");
+        write_atomically(&self.path(key), &data)
+    }
+
+    fn remove(&self, key: &str) -> Result<(), CoreError> {
+        let _lock = self.write_lock.lock().expect("Storage lock poisoned");
+        match fs::remove_file(self.path(key)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error("Couldn't remove stored value", e)),
+        }
+    }
+}
+
+fn aad(key: &str) -> Vec<u8> {
+    let mut aad = vec![VERSION];
+    aad.extend(key.as_bytes());
+    aad
+}
+
+fn read_or_create_salt(path: &Path) -> Result<Vec<u8>, CoreError> {
+    match fs::read(path) {
+        Ok(salt) if salt.len() == SALT_LEN => Ok(salt),
+        Ok(salt) => Err(CoreError::General(format!(
+            "Invalid storage salt: {} bytes",
+            salt.len()
+        ))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let salt = crypto::random_bytes(SALT_LEN);
+            write_atomically(path, &salt)?;
+            Ok(salt)
+        }
+        Err(e) => Err(io_error("Couldn't read storage salt", e)),
+    }
+}
+
+// Through a temporary file, so a crash doesn't leave a partially written file
+fn write_atomically(path: &Path, data: &[u8]) -> Result<(), CoreError> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, data)
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .map_err(|e| io_error("Couldn't write stored value", e))
+}
+
+fn io_error(message: &str, e: io::Error) -> CoreError {
+    CoreError::General(format!("{}: {:?}", message, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn dir() -> PathBuf {
+        std::env::temp_dir().join(format!("core-storage-{}", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn stores_values_across_instances() {
+        let dir = dir();
+        let storage = EncryptedFileStorage::open(&dir, "password").unwrap();
+
+        storage.set("session", "value").unwrap();
+        storage.set("session", "new value").unwrap();
+
+        let storage = EncryptedFileStorage::open(&dir, "password").unwrap();
+        assert_eq!(
+            storage.get("session").unwrap(),
+            Some("new value".to_owned())
+        );
+        storage.remove("session").unwrap();
+        assert_eq!(storage.get("session").unwrap(), None);
+        // Nothing to remove
+        assert!(storage.remove("session").is_ok());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn encrypts_values() {
+        let dir = dir();
+        let storage = EncryptedFileStorage::open(&dir, "password").unwrap();
+
+        storage.set("session", "secret value").unwrap();
+
+        let data = fs::read(storage.path("session")).unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("secret"));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        let dir = dir();
+        EncryptedFileStorage::open(&dir, "password")
+            .unwrap()
+            .set("session", "value")
+            .unwrap();
+
+        let storage = EncryptedFileStorage::open(&dir, "other password").unwrap();
+
+        assert!(storage.get("session").is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_value_of_other_key() {
+        let dir = dir();
+        let storage = EncryptedFileStorage::open(&dir, "password").unwrap();
+        storage.set("a", "value").unwrap();
+
+        fs::rename(storage.path("a"), storage.path("b")).unwrap();
+
+        assert!(storage.get("b").is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,562 @@
+use crate::{
+    config::{CoreConfig, EventsConfig},
+    errors::CoreError,
+    networking_async::AsyncRemoteSessionApi,
+};
+use log::*;
+use ploc_common::errors::NetworkingError;
+use reqwest::{
+    header::{ACCEPT, CACHE_CONTROL},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::{delay_for, timeout};
+
+const EVENTS_PATH: &str = "events";
+
+// Session updates pushed by the backend (server-sent events), so the apps don't have to poll
+// participants / ack to know when the peer joined or acked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    PeerJoined { key: String },
+    PeerAcked,
+    SessionDeleted,
+}
+
+// How a connection to the event stream ended
+#[derive(Debug)]
+enum StreamEnd {
+    // Received SessionDeleted: no more events for this session
+    SessionEnded,
+    // Closed by the server or idle for too long, we can reconnect
+    Disconnected,
+    // Not supported by the backend or failed to reconnect (see stream_with_reconnects)
+    Unavailable,
+}
+
+#[derive(Debug)]
+enum PollEnd {
+    SessionDeleted,
+    // Time to retry the event stream
+    RetryEvents,
+}
+
+pub struct SessionEventsApi {
+    config: CoreConfig,
+    // Separate client: the event stream is long-lived, so it can't use the request timeout
+    client: Client,
+}
+
+impl SessionEventsApi {
+    pub fn new(config: CoreConfig) -> Result<SessionEventsApi, CoreError> {
+        let client = Client::builder()
+            .connect_timeout(config.connect_timeout())
+            .build()?;
+        Ok(SessionEventsApi { config, client })
+    }
+
+    // Delivers the events of the session to on_event until the session is deleted.
+    // Reconnects if the stream is interrupted. If the backend doesn't support events or we
+    // can't reconnect, falls back to polling participants (using api), retrying the stream with
+    // backoff (acks are only received through the stream).
+    // own_key is used to not report our own join as PeerJoined.
+    pub async fn watch<A, F>(
+        &self,
+        api: &A,
+        session_id: String,
+        own_key: String,
+        on_event: F,
+    ) -> Result<(), CoreError>
+    where
+        A: AsyncRemoteSessionApi + Sync,
+        F: Fn(SessionEvent) + Send + Sync,
+    {
+        let config = &self.config.events;
+        // Reported peers, by the stream or polling
+        let peer_keys = Mutex::new(HashSet::new());
+        let on_event = |event: SessionEvent| {
+            if let SessionEvent::PeerJoined { key } = &event {
+                let mut peer_keys = peer_keys.lock().expect("Peer keys lock poisoned");
+                if key == &own_key || !peer_keys.insert(key.clone()) {
+                    return;
+                }
+            }
+            on_event(event)
+        };
+
+        let mut retry_interval = config.reconnect_interval();
+        loop {
+            if let StreamEnd::SessionEnded =
+                self.stream_with_reconnects(&session_id, &on_event).await
+            {
+                return Ok(());
+            }
+            info!(
+                "Polling session: {}, retrying events in {:?}",
+                session_id, retry_interval
+            );
+            let polling = Polling {
+                api,
+                config,
+                session_id: &session_id,
+                own_key: &own_key,
+                peer_keys: &peer_keys,
+            };
+            if let PollEnd::SessionDeleted = polling.poll(&on_event, retry_interval).await? {
+                return Ok(());
+            }
+            retry_interval = (retry_interval * 2).min(config.max_reconnect_interval());
+        }
+    }
+
+    // Returns Unavailable if the backend doesn't support events or we couldn't reconnect
+    async fn stream_with_reconnects<F>(&self, session_id: &str, on_event: &F) -> StreamEnd
+    where
+        F: Fn(SessionEvent),
+    {
+        let config = &self.config.events;
+        let mut failures = 0;
+        loop {
+            match self.stream(session_id, on_event).await {
+                Ok(StreamEnd::Disconnected) => {
+                    info!("Session events stream disconnected, reconnecting");
+                    failures = 0;
+                }
+                Ok(end) => return end,
+                Err(e) if is_unsupported(&e) => {
+                    info!("Backend doesn't support session events: {:?}", e);
+                    return StreamEnd::Unavailable;
+                }
+                Err(e) => {
+                    failures += 1;
+                    warn!(
+                        "Session events stream failed ({} of {}): {:?}",
+                        failures, config.max_reconnect_attempts, e
+                    );
+                    if failures >= config.max_reconnect_attempts {
+                        return StreamEnd::Unavailable;
+                    }
+                }
+            }
+            delay_for(config.reconnect_interval()).await;
+        }
+    }
+
+    async fn stream<F>(&self, session_id: &str, on_event: &F) -> Result<StreamEnd, CoreError>
+    where
+        F: Fn(SessionEvent),
+    {
+        let config = &self.config.events;
+        let mut response = self
+            .client
+            .get(&self.config.url(EVENTS_PATH))
+            .query(&[("session_id", session_id)])
+            .header(ACCEPT, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(CoreError::Networking(NetworkingError {
+                http_status: response.status().as_u16(),
+                message: format!("Couldn't connect to session events: {}", response.status()),
+            }));
+        }
+        info!("Connected to session events: {}", session_id);
+
+        let mut parser = SseParser::default();
+        loop {
+            let chunk = match timeout(config.idle_timeout(), response.chunk()).await {
+                Ok(chunk) => chunk?,
+                Err(_) => {
+                    warn!(
+                        "No data from session events for {:?}",
+                        config.idle_timeout()
+                    );
+                    return Ok(StreamEnd::Disconnected);
+                }
+            };
+            let chunk = match chunk {
+                Some(chunk) => chunk,
+                None => return Ok(StreamEnd::Disconnected),
+            };
+
+            for data in parser.feed(&chunk) {
+                match serde_json::from_str::<SessionEvent>(&data) {
+                    Ok(event) => {
+                        debug!("Session event: {:?}", event);
+                        let ended = event == SessionEvent::SessionDeleted;
+                        on_event(event);
+                        if ended {
+                            return Ok(StreamEnd::SessionEnded);
+                        }
+                    }
+                    Err(e) => warn!("Ignoring unknown session event: {}, error: {:?}", data, e),
+                }
+            }
+        }
+    }
+}
+
+// Polling can only detect joined peers and deleted sessions: acks are not visible in participants,
+// they are received once the stream is back (see SessionEventsApi::watch).
+struct Polling<'a, A> {
+    api: &'a A,
+    config: &'a EventsConfig,
+    session_id: &'a str,
+    own_key: &'a str,
+    // Reported peers (see SessionEventsApi::watch)
+    peer_keys: &'a Mutex<HashSet<String>>,
+}
+
+impl<'a, A> Polling<'a, A>
+where
+    A: AsyncRemoteSessionApi + Sync,
+{
+    // Polls participants until the session is deleted or for the duration
+    async fn poll<F>(&self, on_event: &F, duration: Duration) -> Result<PollEnd, CoreError>
+    where
+        F: Fn(SessionEvent),
+    {
+        let end = Instant::now() + duration;
+        // Only if we saw our key: we may be polling before the join completed
+        let mut own_key_seen = false;
+        loop {
+            match self.api.participants(self.session_id.to_owned()).await {
+                Ok(session) => {
+                    let keys: HashSet<String> = session.keys.into_iter().map(|k| k.str).collect();
+                    own_key_seen |= keys.contains(self.own_key);
+                    if self.is_deleted(&keys, own_key_seen) {
+                        on_event(SessionEvent::SessionDeleted);
+                        return Ok(PollEnd::SessionDeleted);
+                    }
+                    for key in keys {
+                        on_event(SessionEvent::PeerJoined { key });
+                    }
+                }
+                Err(CoreError::SessionNotFound) => {
+                    on_event(SessionEvent::SessionDeleted);
+                    return Ok(PollEnd::SessionDeleted);
+                }
+                // Already retried by the api, keep polling
+                Err(e @ CoreError::Networking(_)) | Err(e @ CoreError::RetriesExhausted { .. }) => {
+                    warn!("Polling session failed: {:?}", e)
+                }
+                Err(e) => return Err(e),
+            }
+            if Instant::now() >= end {
+                return Ok(PollEnd::RetryEvents);
+            }
+            delay_for(self.config.polling_interval()).await;
+        }
+    }
+
+    // The backend doesn't report deletions: the session has no keys anymore, so a key we saw
+    // (the peer's or ours) disappears
+    fn is_deleted(&self, keys: &HashSet<String>, own_key_seen: bool) -> bool {
+        let peer_keys = self.peer_keys.lock().expect("Peer keys lock poisoned");
+        (own_key_seen && !keys.contains(self.own_key))
+            || peer_keys.iter().any(|key| !keys.contains(key))
+    }
+}
+
+fn is_unsupported(error: &CoreError) -> bool {
+    match error {
+        CoreError::Networking(e) => [
+            StatusCode::NOT_FOUND.as_u16(),
+            StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+            StatusCode::NOT_IMPLEMENTED.as_u16(),
+        ]
+        .contains(&e.http_status),
+        _ => false,
+    }
+}
+
+// Minimal server-sent events parser: returns the data of the complete events.
+// Event types and ids aren't used, the type is in the (JSON) data.
+#[derive(Debug, Default)]
+struct SseParser {
+    // Incomplete line (chunks can end anywhere)
+    line: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        let mut events = vec![];
+        for byte in bytes {
+            if *byte != b'\n' {
+                self.line.push(*byte);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.line)
+                .trim_end_matches('\r')
+                .to_owned();
+            self.line.clear();
+
+            if line.is_empty() {
+                // Blank line dispatches the event
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data.push(value.trim_start_matches(' ').to_owned());
+            }
+            // Other fields and comments (":", used as keep alive) are ignored
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto,
+        globals::{ClientSessionKey, KeyPair},
+        mock_backend::MockBackend,
+        networking_async::AsyncRemoteSessionApiImpl,
+    };
+    use ploc_common::model_types::PublicKey;
+    use std::future::Future;
+    use tokio::runtime::Runtime;
+
+    const SESSION_ID: &str = "1";
+
+    struct Fixture {
+        backend: MockBackend,
+        api: AsyncRemoteSessionApiImpl,
+        events_api: SessionEventsApi,
+    }
+
+    fn runtime() -> Runtime {
+        tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    fn fixture(runtime: &Runtime) -> Fixture {
+        let backend = MockBackend::start();
+        let (api, events_api) = runtime.enter(|| {
+            (
+                AsyncRemoteSessionApiImpl::new(backend.config()).unwrap(),
+                SessionEventsApi::new(backend.config()).unwrap(),
+            )
+        });
+        Fixture {
+            backend,
+            api,
+            events_api,
+        }
+    }
+
+    fn key_pair() -> KeyPair {
+        crypto::create_key_pair().unwrap()
+    }
+
+    fn peer_joined(key_pair: &KeyPair) -> SessionEvent {
+        SessionEvent::PeerJoined {
+            key: key_pair.public.clone(),
+        }
+    }
+
+    async fn join(api: &AsyncRemoteSessionApiImpl, key_pair: &KeyPair) {
+        let session_key = ClientSessionKey {
+            session_id: SESSION_ID.to_owned(),
+            key: PublicKey {
+                str: key_pair.public.clone(),
+            },
+        };
+        api.join_session(session_key, &key_pair.private)
+            .await
+            .unwrap();
+    }
+
+    async fn delete(api: &AsyncRemoteSessionApiImpl, key_pair: &KeyPair) {
+        let peer_id = MockBackend::peer_id(&key_pair.public);
+        api.delete(peer_id, &key_pair.private).await.unwrap();
+    }
+
+    async fn pause() {
+        delay_for(Duration::from_millis(50)).await;
+    }
+
+    // Watches the session as own while driver runs. Returns the received events.
+    fn watch<D: Future<Output = ()>>(
+        runtime: &mut Runtime,
+        fixture: &Fixture,
+        own: &KeyPair,
+        driver: D,
+    ) -> Vec<SessionEvent> {
+        let events = Mutex::new(vec![]);
+        let watch = fixture.events_api.watch(
+            &fixture.api,
+            SESSION_ID.to_owned(),
+            own.public.clone(),
+            |event| events.lock().unwrap().push(event),
+        );
+        let (res, _) = runtime
+            .block_on(async { futures::join!(timeout(Duration::from_secs(5), watch), driver) });
+
+        assert!(matches!(res, Ok(Ok(()))), "{:?}", res);
+        events.into_inner().unwrap()
+    }
+
+    #[test]
+    fn reconnects_after_disconnect() {
+        let mut runtime = runtime();
+        let fixture = fixture(&runtime);
+        let (own, peer) = (key_pair(), key_pair());
+        fixture
+            .backend
+            .push_events(vec![peer_joined(&own), peer_joined(&peer)]);
+        fixture.backend.push_events(vec![
+            peer_joined(&peer),
+            SessionEvent::PeerAcked,
+            SessionEvent::SessionDeleted,
+        ]);
+
+        let events = watch(&mut runtime, &fixture, &own, async {});
+
+        assert_eq!(
+            events,
+            vec![
+                peer_joined(&peer),
+                SessionEvent::PeerAcked,
+                SessionEvent::SessionDeleted
+            ]
+        );
+        assert_eq!(fixture.backend.requests("events"), 2);
+        assert_eq!(fixture.backend.requests("part"), 0);
+    }
+
+    #[test]
+    fn polls_if_events_are_unsupported() {
+        let mut runtime = runtime();
+        let fixture = fixture(&runtime);
+        let (own, peer) = (key_pair(), key_pair());
+        runtime.block_on(join(&fixture.api, &own));
+
+        let events = watch(&mut runtime, &fixture, &own, async {
+            pause().await;
+            join(&fixture.api, &peer).await;
+            pause().await;
+            delete(&fixture.api, &own).await;
+            delete(&fixture.api, &peer).await;
+        });
+
+        assert_eq!(
+            events,
+            vec![peer_joined(&peer), SessionEvent::SessionDeleted]
+        );
+        assert!(fixture.backend.requests("part") > 1);
+    }
+
+    #[test]
+    fn reports_deletion_when_reported_peer_key_disappears() {
+        let mut runtime = runtime();
+        let fixture = fixture(&runtime);
+        let (own, peer) = (key_pair(), key_pair());
+        // Then disconnects and events aren't available anymore: polling finds no keys
+        fixture.backend.push_events(vec![peer_joined(&peer)]);
+
+        let events = watch(&mut runtime, &fixture, &own, async {});
+
+        assert_eq!(
+            events,
+            vec![peer_joined(&peer), SessionEvent::SessionDeleted]
+        );
+    }
+
+    #[test]
+    fn retries_events_while_polling() {
+        let mut runtime = runtime();
+        let fixture = fixture(&runtime);
+        let own = key_pair();
+        runtime.block_on(join(&fixture.api, &own));
+
+        let events = watch(&mut runtime, &fixture, &own, async {
+            pause().await;
+            fixture
+                .backend
+                .push_events(vec![SessionEvent::PeerAcked, SessionEvent::SessionDeleted]);
+        });
+
+        // Acks can't be polled
+        assert_eq!(
+            events,
+            vec![SessionEvent::PeerAcked, SessionEvent::SessionDeleted]
+        );
+        assert!(fixture.backend.requests("events") > 1);
+        assert!(fixture.backend.requests("part") > 0);
+    }
+
+    #[test]
+    fn falls_back_to_polling_after_max_reconnect_attempts() {
+        let mut runtime = runtime();
+        let fixture = fixture(&runtime);
+        for _ in 0..3 {
+            fixture.backend.fail_next("events", 500);
+        }
+        fixture
+            .backend
+            .push_events(vec![SessionEvent::SessionDeleted]);
+
+        let events = watch(&mut runtime, &fixture, &key_pair(), async {});
+
+        assert_eq!(events, vec![SessionEvent::SessionDeleted]);
+        // The 4th connection is the retry after polling
+        assert_eq!(fixture.backend.requests("events"), 4);
+        assert!(fixture.backend.requests("part") > 0);
+    }
+
+    #[test]
+    fn parses_events_split_across_chunks() {
+        let mut parser = SseParser::default();
+
+        assert!(parser
+            .feed(b": keep alive\n\ndata: {\"type\": \"peer_")
+            .is_empty());
+        let events = parser.feed(b"acked\"}\r\n\r\ndata: {\"type\":\"session_deleted\"}\n\n");
+
+        assert_eq!(
+            events,
+            vec![
+                r#"{"type": "peer_acked"}"#.to_owned(),
+                r#"{"type":"session_deleted"}"#.to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let mut parser = SseParser::default();
+        let events = parser.feed(b"event: update\ndata: {\"type\":\ndata: \"peer_acked\"}\n\n");
+        assert_eq!(events, vec!["{\"type\":\n\"peer_acked\"}".to_owned()]);
+    }
+
+    #[test]
+    fn deserializes_events() {
+        let event: SessionEvent =
+            serde_json::from_str(r#"{"type": "peer_joined", "key": "-----BEGIN PUBLIC KEY..."}"#)
+                .unwrap();
+        assert_eq!(
+            event,
+            SessionEvent::PeerJoined {
+                key: "-----BEGIN PUBLIC KEY...".to_owned()
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&SessionEvent::SessionDeleted).unwrap(),
+            r#"{"type":"session_deleted"}"#
+        );
+    }
+}
@@ -0,0 +1,77 @@
+// Ids of the backend sessions. Generated by the creator and shared with the joiner, e.g. in a deep
+// link or QR code: the ids the core receives are untrusted input, validated before any request.
+//
+// Format: 32 hex characters (128 random bits), like the UUIDs without dashes the apps generated
+// before. Uppercase when generated, either case accepted.
+
+use crate::{crypto, errors::CoreError};
+
+const SESSION_ID_BYTES: usize = 16;
+
+pub fn generate_session_id() -> String {
+    crypto::random_bytes(SESSION_ID_BYTES)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
+
+pub fn validate_session_id(session_id: &str) -> Result<(), CoreError> {
+    let is_valid = session_id.len() == SESSION_ID_BYTES * 2
+        && session_id.chars().all(|c| c.is_ascii_hexdigit());
+    if is_valid {
+        Ok(())
+    } else {
+        // Not the id itself: it's untrusted, can be anything
+        Err(CoreError::InvalidSessionId {
+            len: session_id.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_valid_unique_ids() {
+        let id = generate_session_id();
+
+        assert_eq!(id.len(), 32);
+        assert!(validate_session_id(&id).is_ok());
+        assert_eq!(id, id.to_uppercase());
+        assert_ne!(id, generate_session_id());
+    }
+
+    #[test]
+    fn accepts_ids_of_older_app_versions() {
+        // Swift's UUID().uuidString without dashes, and lowercase
+        assert!(validate_session_id("E621E1F8C36C495A93FC0C247A3E6E5F").is_ok());
+        assert!(validate_session_id("e621e1f8c36c495a93fc0c247a3e6e5f").is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_ids() {
+        let invalid = [
+            "",
+            "E621E1F8-C36C-495A-93FC-0C247A3E6E5F",
+            "E621E1F8C36C495A93FC0C247A3E6E5",
+            "E621E1F8C36C495A93FC0C247A3E6E5F0",
+            "E621E1F8C36C495A93FC0C247A3E6E5G",
+            " E621E1F8C36C495A93FC0C247A3E6E5F",
+            "../../../del?peer_id=E621E1F8C36C",
+            // Non-ASCII digits
+            "E621E1F8C36C495A93FC0C247A3E6E٥",
+        ];
+
+        for id in &invalid {
+            assert!(
+                matches!(
+                    validate_session_id(id),
+                    Err(CoreError::InvalidSessionId { .. })
+                ),
+                "{}",
+                id
+            );
+        }
+    }
+}
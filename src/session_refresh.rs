@@ -0,0 +1,351 @@
+use crate::{
+    crypto::{self, PrivateKey},
+    errors::CoreError,
+    networking::RemoteSessionApi,
+    networking_async::AsyncRemoteSessionApi,
+};
+use log::*;
+use ploc_common::model_types::PublicKey;
+use serde::Serialize;
+
+// Local session state needed to refresh. The apps own the session storage,
+// so they pass it and store the peer key from the result.
+#[derive(Debug)]
+pub struct RefreshSessionParams {
+    pub session_id: String,
+    pub own_key: String,
+    // Signs ack / delete
+    pub own_private_key: PrivateKey,
+    // Peer's key if already stored
+    pub peer_key: Option<String>,
+}
+
+// Where the session is after a refresh. All states with a peer include its key,
+// which the app should store if it doesn't have it yet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SessionRefresh {
+    // The peer hasn't joined yet. Nothing was acked.
+    WaitingForPeer,
+    // Received the peer's key in this refresh and acked it, peer hasn't acked ours yet.
+    PeerKeyReceived {
+        peer_key: String,
+    },
+    // We had the peer's key and acked it (again), peer hasn't acked ours yet.
+    WaitingForPeerAck {
+        peer_key: String,
+    },
+    // Both peers acked having stored the key of the other. The backend session was marked as
+    // deleted, unless delete_failed: the apps should retry it (see globals::delete).
+    Ready {
+        peer_key: String,
+        delete_failed: bool,
+    },
+}
+
+// Fetches the participants, determines the peer, acks and, if both peers acked, marks the
+// backend session as deleted. Can be called repeatedly (e.g. to retry after errors): acking again
+// returns whether the peer acked in the meantime.
+pub fn refresh_session<A: RemoteSessionApi>(
+    api: &A,
+    params: RefreshSessionParams,
+) -> Result<SessionRefresh, CoreError> {
+    let session = api.participants(params.session_id.clone())?;
+    let peer_key = match determine_peer(&params, session.keys)? {
+        Some(peer_key) => peer_key,
+        None => return Ok(SessionRefresh::WaitingForPeer),
+    };
+
+    let peer_id = own_peer_id(&params);
+    let private_key = &params.own_private_key;
+    let is_ready = api.ack(peer_id.clone(), 2, private_key)?;
+    let delete_failed = is_ready && delete_result(api.delete(peer_id, private_key));
+    Ok(to_refresh(&params, peer_key, is_ready, delete_failed))
+}
+
+pub async fn refresh_session_async<A: AsyncRemoteSessionApi + Sync>(
+    api: &A,
+    params: RefreshSessionParams,
+) -> Result<SessionRefresh, CoreError> {
+    let session = api.participants(params.session_id.clone()).await?;
+    let peer_key = match determine_peer(&params, session.keys)? {
+        Some(peer_key) => peer_key,
+        None => return Ok(SessionRefresh::WaitingForPeer),
+    };
+
+    let peer_id = own_peer_id(&params);
+    let private_key = &params.own_private_key;
+    let is_ready = api.ack(peer_id.clone(), 2, private_key).await?;
+    let delete_failed = is_ready && delete_result(api.delete(peer_id, private_key).await);
+    Ok(to_refresh(&params, peer_key, is_ready, delete_failed))
+}
+
+// Identifies us in ack / delete
+fn own_peer_id(params: &RefreshSessionParams) -> String {
+    crypto::peer_id(&PublicKey {
+        str: params.own_key.clone(),
+    })
+}
+
+// The backend session has at most 2 keys: ours and (once joined) the peer's.
+fn determine_peer(
+    params: &RefreshSessionParams,
+    keys: Vec<PublicKey>,
+) -> Result<Option<String>, CoreError> {
+    if keys.len() > 2 {
+        error!(
+            "Invalid state: more than 2 peers in the session: {:?}",
+            keys
+        );
+        return Err(CoreError::SessionFull);
+    }
+
+    let mut other_keys: Vec<String> = keys
+        .into_iter()
+        .map(|k| k.str)
+        .filter(|k| k != &params.own_key)
+        .collect();
+    if other_keys.len() > 1 {
+        return Err(CoreError::General(format!(
+            "Invalid state: backend session keys don't include mine: {:?}",
+            other_keys
+        )));
+    }
+    let backend_peer_key = other_keys.pop();
+
+    match (&params.peer_key, backend_peer_key) {
+        (Some(stored), Some(received)) if stored != &received => Err(CoreError::General(format!(
+            "Invalid state: peer key changed, stored: {}, received: {}",
+            stored, received
+        ))),
+        // The backend doesn't return the keys anymore after deleting, keep using the stored one
+        (Some(stored), _) => Ok(Some(stored.clone())),
+        (None, received) => Ok(received),
+    }
+}
+
+// Marking as deleted is only cleanup, the session is ready anyway. Returns whether it failed.
+fn delete_result(res: Result<(), CoreError>) -> bool {
+    match res {
+        Ok(()) => false,
+        Err(e) => {
+            error!("Session is ready, but couldn't mark it as deleted: {:?}", e);
+            true
+        }
+    }
+}
+
+fn to_refresh(
+    params: &RefreshSessionParams,
+    peer_key: String,
+    is_ready: bool,
+    delete_failed: bool,
+) -> SessionRefresh {
+    if is_ready {
+        SessionRefresh::Ready {
+            peer_key,
+            delete_failed,
+        }
+    } else if params.peer_key.is_none() {
+        SessionRefresh::PeerKeyReceived { peer_key }
+    } else {
+        SessionRefresh::WaitingForPeerAck { peer_key }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        globals::{ClientSessionKey, KeyPair},
+        mock_backend::MockBackend,
+        networking::{RemoteSessionApiImpl, Session},
+    };
+    use std::{cell::RefCell, collections::VecDeque};
+
+    #[derive(Default)]
+    struct FakeApi {
+        keys: Vec<&'static str>,
+        ack_results: RefCell<VecDeque<Result<bool, CoreError>>>,
+        acks: RefCell<Vec<(String, i32)>>,
+        deletes: RefCell<Vec<String>>,
+        fail_delete: bool,
+    }
+
+    impl RemoteSessionApi for FakeApi {
+        fn join_session(&self, _: ClientSessionKey, _: &PrivateKey) -> Result<Session, CoreError> {
+            Err(CoreError::General("Not supported by FakeApi".to_owned()))
+        }
+
+        fn ack(&self, uuid: String, count: i32, _: &PrivateKey) -> Result<bool, CoreError> {
+            self.acks.borrow_mut().push((uuid, count));
+            self.ack_results.borrow_mut().pop_front().unwrap()
+        }
+
+        fn participants(&self, session_id: String) -> Result<Session, CoreError> {
+            Ok(Session {
+                id: session_id,
+                keys: self.keys.iter().map(|k| key(k)).collect(),
+            })
+        }
+
+        fn delete(&self, peer_id: String, _: &PrivateKey) -> Result<(), CoreError> {
+            self.deletes.borrow_mut().push(peer_id);
+            if self.fail_delete {
+                Err(CoreError::General("Delete failed".to_owned()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn api(keys: Vec<&'static str>, ack_results: Vec<Result<bool, CoreError>>) -> FakeApi {
+        FakeApi {
+            keys,
+            ack_results: RefCell::new(ack_results.into()),
+            ..FakeApi::default()
+        }
+    }
+
+    fn key(str: &str) -> PublicKey {
+        PublicKey {
+            str: str.to_owned(),
+        }
+    }
+
+    fn params(peer_key: Option<&str>) -> RefreshSessionParams {
+        RefreshSessionParams {
+            session_id: "session".to_owned(),
+            own_key: "my_key".to_owned(),
+            own_private_key: PrivateKey::from("my_private_key"),
+            peer_key: peer_key.map(|k| k.to_owned()),
+        }
+    }
+
+    #[test]
+    fn waits_for_peer_without_acking() {
+        let api = api(vec!["my_key"], vec![]);
+
+        let res = refresh_session(&api, params(None));
+
+        assert_eq!(res.unwrap(), SessionRefresh::WaitingForPeer);
+        assert!(api.acks.borrow().is_empty());
+    }
+
+    #[test]
+    fn acks_received_peer_key() {
+        let api = api(vec!["my_key", "peer_key"], vec![Ok(false)]);
+
+        let res = refresh_session(&api, params(None));
+
+        assert_eq!(
+            res.unwrap(),
+            SessionRefresh::PeerKeyReceived {
+                peer_key: "peer_key".to_owned()
+            }
+        );
+        assert_eq!(
+            *api.acks.borrow(),
+            vec![(crypto::peer_id(&key("my_key")), 2)]
+        );
+        assert!(api.deletes.borrow().is_empty());
+    }
+
+    #[test]
+    fn ready_after_peer_acked_our_ack() {
+        let backend = MockBackend::start();
+        let api = RemoteSessionApiImpl::new(backend.config()).unwrap();
+        let (peer_a, peer_b) = (
+            crypto::create_key_pair().unwrap(),
+            crypto::create_key_pair().unwrap(),
+        );
+        for peer in &[&peer_a, &peer_b] {
+            let session_key = ClientSessionKey {
+                session_id: "session".to_owned(),
+                key: key(&peer.public),
+            };
+            api.join_session(session_key, &peer.private).unwrap();
+        }
+        let refresh = |own: &KeyPair, stored_peer_key: Option<String>| {
+            let params = RefreshSessionParams {
+                session_id: "session".to_owned(),
+                own_key: own.public.clone(),
+                own_private_key: PrivateKey::from(own.private.expose()),
+                peer_key: stored_peer_key,
+            };
+            refresh_session(&api, params).unwrap()
+        };
+
+        let a_acked = refresh(&peer_a, None);
+        let b_acked = refresh(&peer_b, None);
+        let a_refreshed = refresh(&peer_a, Some(peer_b.public.clone()));
+
+        assert_eq!(
+            a_acked,
+            SessionRefresh::PeerKeyReceived {
+                peer_key: peer_b.public.clone()
+            }
+        );
+        assert!(matches!(b_acked, SessionRefresh::Ready { .. }));
+        assert_eq!(
+            a_refreshed,
+            SessionRefresh::Ready {
+                peer_key: peer_b.public.clone(),
+                delete_failed: false
+            }
+        );
+    }
+
+    #[test]
+    fn ready_marks_session_as_deleted() {
+        let api = api(vec!["my_key", "peer_key"], vec![Ok(true)]);
+
+        let res = refresh_session(&api, params(Some("peer_key")));
+
+        assert_eq!(
+            res.unwrap(),
+            SessionRefresh::Ready {
+                peer_key: "peer_key".to_owned(),
+                delete_failed: false
+            }
+        );
+        assert_eq!(*api.deletes.borrow(), vec![crypto::peer_id(&key("my_key"))]);
+    }
+
+    #[test]
+    fn ready_reports_failed_delete() {
+        let api = FakeApi {
+            fail_delete: true,
+            ..api(vec!["my_key", "peer_key"], vec![Ok(true)])
+        };
+
+        let res = refresh_session(&api, params(Some("peer_key")));
+
+        assert_eq!(
+            res.unwrap(),
+            SessionRefresh::Ready {
+                peer_key: "peer_key".to_owned(),
+                delete_failed: true
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_more_than_two_keys() {
+        let api = api(vec!["my_key", "peer_key", "other_key"], vec![]);
+
+        let res = refresh_session(&api, params(None));
+
+        assert!(matches!(res, Err(CoreError::SessionFull)));
+    }
+
+    #[test]
+    fn rejects_changed_peer_key() {
+        let api = api(vec!["my_key", "other_key"], vec![]);
+
+        let res = refresh_session(&api, params(Some("peer_key")));
+
+        assert!(matches!(res, Err(CoreError::General(_))));
+        assert!(api.acks.borrow().is_empty());
+    }
+}
@@ -0,0 +1,415 @@
+// Lifecycle of a session, as seen by this peer. Driven by the results of the session operations
+// (see globals), which are rejected if they aren't valid in the session's current state.
+//
+// Created -> WaitingForPeer -> PeerJoined -> Acked -> Ready -> Meeting -> Ended
+// Joining after the peer goes directly to PeerJoined, acking after the peer directly to Ready.
+// Deleting before Ready abandons the pairing (-> Deleted). Once Ready, it only cleans up the backend
+// session (see session_refresh): the session itself is still used for the meeting.
+
+use crate::{client_session::ClientSession, errors::CoreError};
+use log::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    // Not joined in the backend yet
+    Created,
+    WaitingForPeer,
+    // We have the peer's key
+    PeerJoined,
+    // We acked the peer's key, the peer hasn't acked ours yet
+    Acked,
+    // Both peers acked the key of the other
+    Ready,
+    Meeting,
+    Ended,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionOperation {
+    Join,
+    Participants,
+    Ack,
+    Delete,
+    StartMeeting,
+    EndMeeting,
+}
+
+// Successful result of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionLifecycleEvent {
+    // has_peer: the backend session has the peer's key
+    Joined { has_peer: bool },
+    ParticipantsFetched { has_peer: bool },
+    Acked { is_ready: bool },
+    Deleted,
+    MeetingStarted,
+    MeetingEnded,
+}
+
+impl SessionLifecycleEvent {
+    pub fn operation(&self) -> SessionOperation {
+        match self {
+            SessionLifecycleEvent::Joined { .. } => SessionOperation::Join,
+            SessionLifecycleEvent::ParticipantsFetched { .. } => SessionOperation::Participants,
+            SessionLifecycleEvent::Acked { .. } => SessionOperation::Ack,
+            SessionLifecycleEvent::Deleted => SessionOperation::Delete,
+            SessionLifecycleEvent::MeetingStarted => SessionOperation::StartMeeting,
+            SessionLifecycleEvent::MeetingEnded => SessionOperation::EndMeeting,
+        }
+    }
+}
+
+impl SessionState {
+    // Checked before doing the operation, e.g. to not ack before having the peer's key.
+    pub fn allows(self, operation: SessionOperation) -> bool {
+        use SessionState::*;
+        match operation {
+            // Joining again is allowed, e.g. to retry if the response was lost
+            SessionOperation::Join => matches!(self, Created | WaitingForPeer | PeerJoined),
+            SessionOperation::Participants => {
+                matches!(self, WaitingForPeer | PeerJoined | Acked | Ready)
+            }
+            // Refreshing acks again once ready (see session_refresh)
+            SessionOperation::Ack => matches!(self, PeerJoined | Acked | Ready),
+            SessionOperation::Delete => self != Deleted,
+            SessionOperation::StartMeeting => self == Ready,
+            SessionOperation::EndMeeting => self == Meeting,
+        }
+    }
+
+    // Joined in the backend and not finished. The apps have one active session at a time.
+    pub fn is_active(self) -> bool {
+        !matches!(
+            self,
+            SessionState::Created | SessionState::Ended | SessionState::Deleted
+        )
+    }
+
+    pub fn transition(self, event: SessionLifecycleEvent) -> Result<SessionState, CoreError> {
+        use SessionLifecycleEvent::*;
+        check(self, event.operation())?;
+
+        match event {
+            Joined { has_peer } | ParticipantsFetched { has_peer } => match self {
+                SessionState::Created | SessionState::WaitingForPeer if has_peer => {
+                    Ok(SessionState::PeerJoined)
+                }
+                SessionState::Created => Ok(SessionState::WaitingForPeer),
+                // The backend doesn't return the keys anymore once the session is deleted
+                // (possibly by the peer), so no keys doesn't mean that the peer left.
+                _ => Ok(self),
+            },
+            // Once ready, stays ready (e.g. a late response of an earlier ack)
+            Acked { is_ready } if is_ready || self == SessionState::Ready => {
+                Ok(SessionState::Ready)
+            }
+            Acked { .. } => Ok(SessionState::Acked),
+            Deleted => match self {
+                SessionState::Ready | SessionState::Meeting | SessionState::Ended => Ok(self),
+                _ => Ok(SessionState::Deleted),
+            },
+            MeetingStarted => Ok(SessionState::Meeting),
+            MeetingEnded => Ok(SessionState::Ended),
+        }
+    }
+}
+
+// States of the sessions joined since the core was bootstrapped, by session id.
+// Sessions joined before (e.g. in an earlier app run) aren't tracked: their operations aren't
+// validated, unless resumed from the stored client session (see globals::load_session).
+#[derive(Default)]
+pub struct SessionStates {
+    sessions: HashMap<String, TrackedSession>,
+    // Session being started (see reserve)
+    starting: Option<String>,
+}
+
+struct TrackedSession {
+    // Own peer id: ack and delete identify the session with it
+    peer_id: String,
+    state: SessionState,
+}
+
+impl SessionStates {
+    pub fn state(&self, session_id: &str) -> Option<SessionState> {
+        self.sessions.get(session_id).map(|session| session.state)
+    }
+
+    pub fn session_id(&self, peer_id: &str) -> Option<String> {
+        self.sessions
+            .iter()
+            .find(|(_, session)| session.peer_id == peer_id)
+            .map(|(id, _)| id.clone())
+    }
+
+    pub fn active_session(&self) -> Option<String> {
+        self.sessions
+            .iter()
+            .find(|(_, session)| session.state.is_active())
+            .map(|(id, _)| id.clone())
+    }
+
+    // Before starting a session, with the active session check (see globals::start_session):
+    // concurrent starts of other sessions fail until it's released (when the join completes).
+    pub fn reserve(&mut self, session_id: &str) -> Result<(), CoreError> {
+        match &self.starting {
+            Some(starting) if starting != session_id => Err(CoreError::SessionAlreadyActive {
+                session_id: starting.clone(),
+            }),
+            _ => {
+                self.starting = Some(session_id.to_owned());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn release(&mut self, session_id: &str) {
+        if self.starting.as_deref() == Some(session_id) {
+            self.starting = None;
+        }
+    }
+
+    // Before joining: starts tracking the session, if it isn't yet
+    pub fn check_join(&mut self, session_id: &str, peer_id: &str) -> Result<(), CoreError> {
+        let session = self
+            .sessions
+            .entry(session_id.to_owned())
+            .or_insert_with(|| TrackedSession {
+                peer_id: peer_id.to_owned(),
+                state: SessionState::Created,
+            });
+        check(session.state, SessionOperation::Join)
+    }
+
+    // Starts tracking a stored session, if it isn't yet. The state is derived from what was stored:
+    // whether we acked isn't, nor the meeting.
+    pub fn resume(&mut self, session: &ClientSession) {
+        let state = if session.is_ready {
+            SessionState::Ready
+        } else if session.has_peer() {
+            SessionState::PeerJoined
+        } else {
+            SessionState::WaitingForPeer
+        };
+        self.sessions
+            .entry(session.id.clone())
+            .or_insert_with(|| TrackedSession {
+                peer_id: session.peer_id.clone(),
+                state,
+            });
+    }
+
+    // Stops tracking the session, e.g. when the app abandons it
+    pub fn forget(&mut self, session_id: &str) {
+        self.sessions.remove(session_id);
+    }
+
+    pub fn check(&self, session_id: &str, operation: SessionOperation) -> Result<(), CoreError> {
+        match self.state(session_id) {
+            Some(state) => check(state, operation),
+            None => Ok(()),
+        }
+    }
+
+    // Returns the new state, None if the session isn't tracked
+    pub fn apply(
+        &mut self,
+        session_id: &str,
+        event: SessionLifecycleEvent,
+    ) -> Result<Option<SessionState>, CoreError> {
+        let session = match self.sessions.get_mut(session_id) {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+        let state = session.state.transition(event)?;
+        debug!(
+            "Session {} state: {:?} -> {:?}",
+            session_id, session.state, state
+        );
+        session.state = state;
+        Ok(Some(state))
+    }
+}
+
+fn check(state: SessionState, operation: SessionOperation) -> Result<(), CoreError> {
+    if state.allows(operation) {
+        Ok(())
+    } else {
+        Err(CoreError::InvalidTransition {
+            from: state,
+            operation,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::create_key_pair;
+    use SessionLifecycleEvent::*;
+
+    fn run(events: &[SessionLifecycleEvent]) -> Result<SessionState, CoreError> {
+        events
+            .iter()
+            .try_fold(SessionState::Created, |state, event| {
+                state.transition(*event)
+            })
+    }
+
+    #[test]
+    fn creator_goes_through_all_states() {
+        let res = run(&[
+            Joined { has_peer: false },
+            ParticipantsFetched { has_peer: false },
+            ParticipantsFetched { has_peer: true },
+            Acked { is_ready: false },
+            // The peer acked and deleted the backend session
+            ParticipantsFetched { has_peer: false },
+            Acked { is_ready: true },
+            Deleted,
+            MeetingStarted,
+            MeetingEnded,
+        ]);
+
+        assert_eq!(res.unwrap(), SessionState::Ended);
+    }
+
+    #[test]
+    fn joiner_skips_waiting_states() {
+        let res = run(&[
+            Joined { has_peer: true },
+            Acked { is_ready: true },
+            // Refreshing again
+            Acked { is_ready: false },
+        ]);
+
+        assert_eq!(res.unwrap(), SessionState::Ready);
+    }
+
+    #[test]
+    fn deleting_before_ready_abandons_session() {
+        let state = run(&[Joined { has_peer: false }, Deleted]).unwrap();
+
+        assert_eq!(state, SessionState::Deleted);
+        assert!(!state.allows(SessionOperation::Participants));
+    }
+
+    #[test]
+    fn rejects_invalid_transitions() {
+        let invalid = [
+            // Ack before having the peer's key
+            vec![Joined { has_peer: false }, Acked { is_ready: false }],
+            vec![MeetingStarted],
+            vec![
+                Joined { has_peer: true },
+                Acked { is_ready: false },
+                MeetingStarted,
+            ],
+            vec![
+                Joined { has_peer: true },
+                Acked { is_ready: true },
+                MeetingStarted,
+                ParticipantsFetched { has_peer: true },
+            ],
+            vec![Joined { has_peer: false }, Deleted, Deleted],
+        ];
+
+        for events in &invalid {
+            let res = run(events);
+            assert!(
+                matches!(res, Err(CoreError::InvalidTransition { .. })),
+                "{:?}: {:?}",
+                events,
+                res
+            );
+        }
+    }
+
+    #[test]
+    fn tracks_sessions_by_id_and_peer_id() {
+        let mut states = SessionStates::default();
+
+        states.check_join("session", "peer").unwrap();
+        let state = states.apply("session", Joined { has_peer: true });
+
+        assert_eq!(state.unwrap(), Some(SessionState::PeerJoined));
+        assert_eq!(states.session_id("peer"), Some("session".to_owned()));
+        assert!(states.check("session", SessionOperation::Ack).is_ok());
+        assert!(states
+            .check("session", SessionOperation::EndMeeting)
+            .is_err());
+        // Not tracked: not validated
+        assert!(states.check("other", SessionOperation::Ack).is_ok());
+        assert_eq!(states.apply("other", Deleted).unwrap(), None);
+    }
+
+    #[test]
+    fn resumes_stored_sessions() {
+        let session = |is_ready| {
+            ClientSession::new("session".to_owned(), create_key_pair().unwrap(), true)
+                .with_is_ready(is_ready)
+        };
+        let mut states = SessionStates::default();
+        let waiting = session(false);
+
+        states.resume(&waiting);
+        // Already tracked: keeps the state
+        states.resume(&session(true));
+
+        assert_eq!(states.state("session"), Some(SessionState::WaitingForPeer));
+        assert_eq!(
+            states.session_id(&waiting.peer_id),
+            Some("session".to_owned())
+        );
+        let mut ready_states = SessionStates::default();
+        ready_states.resume(&session(true));
+        assert_eq!(ready_states.state("session"), Some(SessionState::Ready));
+    }
+
+    #[test]
+    fn active_session_is_joined_and_not_finished() {
+        let mut states = SessionStates::default();
+
+        states.check_join("session", "peer").unwrap();
+        // Not joined in the backend yet (e.g. the join failed)
+        assert_eq!(states.active_session(), None);
+        states.apply("session", Joined { has_peer: false }).unwrap();
+        assert_eq!(states.active_session(), Some("session".to_owned()));
+        states.apply("session", Deleted).unwrap();
+        assert_eq!(states.active_session(), None);
+    }
+
+    #[test]
+    fn reserves_session_to_start() {
+        let mut states = SessionStates::default();
+
+        assert!(states.reserve("1").is_ok());
+        // Retrying the same session
+        assert!(states.reserve("1").is_ok());
+        assert!(matches!(
+            states.reserve("2"),
+            Err(CoreError::SessionAlreadyActive { session_id }) if session_id == "1"
+        ));
+        states.release("2");
+        assert!(states.reserve("2").is_err());
+
+        states.release("1");
+        assert!(states.reserve("2").is_ok());
+    }
+
+    #[test]
+    fn forgets_sessions() {
+        let mut states = SessionStates::default();
+        states.check_join("session", "peer").unwrap();
+        states.apply("session", Joined { has_peer: true }).unwrap();
+
+        states.forget("session");
+
+        assert_eq!(states.state("session"), None);
+        assert_eq!(states.active_session(), None);
+    }
+}
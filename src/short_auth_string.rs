@@ -0,0 +1,145 @@
+// Short authentication string (SAS): a code derived from both session public keys, which the users
+// compare out of band (e.g. reading it to each other) once the peer's key was received.
+// The keys are relayed by the backend: if it substituted one, the peers see different codes.
+// Both peers derive the same code, independently of who is "own" and who is "peer".
+
+use crate::{crypto, errors::CoreError};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+
+const INFO: &[u8] = b"ploc sas v1";
+const EMOJI_COUNT: usize = 5;
+
+// 64 emoji: each one encodes 6 bits. Picked to be easy to tell apart and to name.
+const EMOJI: [&str; 64] = [
+    "🐶", "🐱", "🦁", "🐎", "🦄", "🐷", "🐘", "🐰", "🐼", "🐓", "🐧", "🐢", "🐟", "🐙", "🦋", "🌷",
+    "🌳", "🌵", "🍄", "🌏", "🌙", "☁️", "🔥", "🍌", "🍎", "🍓", "🌽", "🍕", "🎂", "❤️", "😀", "🤖",
+    "🎩", "👓", "🔧", "🎅", "👍", "☂️", "⌛", "⏰", "🎁", "💡", "📕", "✏️", "📎", "✂️", "🔒", "🔑",
+    "🔨", "☎️", "🏁", "🚂", "🚲", "✈️", "🚀", "🏆", "⚽", "🎸", "🎺", "🔔", "⚓", "🎧", "📁", "📌",
+];
+
+// Two renderings of the same code, the apps show the one that fits.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShortAuthString {
+    // 6 digits, with leading zeros
+    pub digits: String,
+    pub emoji: Vec<String>,
+}
+
+pub fn short_auth_string(
+    own_key_pem: &str,
+    peer_key_pem: &str,
+) -> Result<ShortAuthString, CoreError> {
+    let own = crypto::key_fingerprint(&crypto::public_key_from_pem(own_key_pem)?);
+    let peer = crypto::key_fingerprint(&crypto::public_key_from_pem(peer_key_pem)?);
+    if own == peer {
+        return Err(CoreError::General(
+            "Peer key is the same as own key".to_owned(),
+        ));
+    }
+    let (first, second) = if own < peer { (own, peer) } else { (peer, own) };
+
+    let hash = Sha256::new()
+        .chain_update(INFO)
+        .chain_update(first)
+        .chain_update(second)
+        .finalize();
+
+    let number = u32::from_be_bytes(hash[..4].try_into().unwrap()) % 1_000_000;
+    // 256 is a multiple of 64, so the emoji aren't biased
+    let emoji = hash[4..4 + EMOJI_COUNT]
+        .iter()
+        .map(|byte| EMOJI[(*byte % 64) as usize].to_owned())
+        .collect();
+
+    Ok(ShortAuthString {
+        digits: format!("{:06}", number),
+        emoji,
+    })
+}
+
+// Checks the code shown by the peer (digits or emoji, entered / confirmed by the user) against ours.
+// AuthStringMismatch means the keys we have aren't the ones the peer has: the session shouldn't
+// be used.
+pub fn check_short_auth_string(
+    own_key_pem: &str,
+    peer_key_pem: &str,
+    peer_code: &str,
+) -> Result<(), CoreError> {
+    let sas = short_auth_string(own_key_pem, peer_key_pem)?;
+    let peer_code: String = peer_code.chars().filter(|c| !c.is_whitespace()).collect();
+    if peer_code == sas.digits || peer_code == sas.emoji.concat() {
+        Ok(())
+    } else {
+        Err(CoreError::AuthStringMismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_peers_derive_same_code() {
+        let (me, peer) = (
+            crypto::create_key_pair().unwrap(),
+            crypto::create_key_pair().unwrap(),
+        );
+
+        let mine = short_auth_string(&me.public, &peer.public).unwrap();
+        let peers = short_auth_string(&peer.public, &me.public).unwrap();
+
+        assert_eq!(mine, peers);
+        assert_eq!(mine.digits.len(), 6);
+        assert!(mine.digits.chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(mine.emoji.len(), EMOJI_COUNT);
+    }
+
+    #[test]
+    fn substituted_key_changes_code() {
+        let (me, peer, attacker) = (
+            crypto::create_key_pair().unwrap(),
+            crypto::create_key_pair().unwrap(),
+            crypto::create_key_pair().unwrap(),
+        );
+
+        // The backend gave us the attacker's key instead of the peer's
+        let mine = short_auth_string(&me.public, &attacker.public).unwrap();
+        let peers = short_auth_string(&peer.public, &attacker.public).unwrap();
+
+        assert_ne!(mine.emoji, peers.emoji);
+        let res = check_short_auth_string(&me.public, &attacker.public, &peers.digits);
+        assert!(matches!(res, Err(CoreError::AuthStringMismatch)));
+    }
+
+    #[test]
+    fn accepts_peer_code_as_digits_or_emoji() {
+        let (me, peer) = (
+            crypto::create_key_pair().unwrap(),
+            crypto::create_key_pair().unwrap(),
+        );
+        let peers = short_auth_string(&peer.public, &me.public).unwrap();
+
+        let spaced_digits = format!("{} {}", &peers.digits[..3], &peers.digits[3..]);
+        assert!(check_short_auth_string(&me.public, &peer.public, &spaced_digits).is_ok());
+        let emoji = peers.emoji.join(" ");
+        assert!(check_short_auth_string(&me.public, &peer.public, &emoji).is_ok());
+    }
+
+    #[test]
+    fn emoji_are_distinct() {
+        let mut emoji = EMOJI.to_vec();
+        emoji.sort_unstable();
+        emoji.dedup();
+        assert_eq!(emoji.len(), 64);
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let key_pair = crypto::create_key_pair().unwrap();
+
+        assert!(short_auth_string(&key_pair.public, "not a key").is_err());
+        assert!(short_auth_string(&key_pair.public, &key_pair.public).is_err());
+    }
+}